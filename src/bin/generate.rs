@@ -0,0 +1,154 @@
+use clap::{Parser, ValueEnum};
+use rollroll::engine::stats::WorldStats;
+use rollroll::engine::svg::world_to_svg;
+use rollroll::engine::world::{GeneratorResult, LevelConfig, TileKind, World};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/* Generates worlds for a range of seeds without opening a window, and reports on each of them,
+ * for tuning the generator offline.
+ */
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Generate many worlds and report statistics about them."
+)]
+struct Cli {
+    #[arg(
+        long,
+        default_value_t = 100,
+        help = "How many worlds to generate, one per seed"
+    )]
+    count: u64,
+
+    #[arg(long, default_value_t = 0, help = "The seed of the first world")]
+    first_seed: u64,
+
+    #[arg(
+        long,
+        default_value_t = 0,
+        conflicts_with = "config",
+        help = "Generate with the usual config for this level number"
+    )]
+    level: usize,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = parse_config,
+        help = "Generate with the world config in this .ron file"
+    )]
+    config: Option<LevelConfig>,
+
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Write the statistics here instead of to standard output"
+    )]
+    output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Also draw every world to an SVG file named after its seed in this directory"
+    )]
+    svg: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+}
+
+fn parse_config(path: &str) -> Result<LevelConfig, String> {
+    LevelConfig::load(Path::new(path)).map_err(|e| e.to_string())
+}
+
+fn generate(config: &LevelConfig, seed: u64) -> (World, Duration) {
+    let start = Instant::now();
+    let mut generator = World::generator(config.clone(), seed);
+    loop {
+        match generator.generate(Duration::from_secs(1)) {
+            GeneratorResult::Generating(next) => generator = next,
+            GeneratorResult::Done(world) => return (world, start.elapsed()),
+        }
+    }
+}
+
+fn csv_header() -> String {
+    let kinds: Vec<String> = TileKind::ALL
+        .iter()
+        .map(|kind| format!("{:?}", kind).to_lowercase())
+        .collect();
+    format!(
+        "seed,tiles,{},open_cells,open_ratio,components,reachable_area,reachable_ratio,\
+         exit_reachable,generation_ms",
+        kinds.join(",")
+    )
+}
+
+fn csv_row(stats: &WorldStats) -> String {
+    let kinds: Vec<String> = stats
+        .tiles_by_kind
+        .values()
+        .map(|count| count.to_string())
+        .collect();
+    format!(
+        "{},{},{},{},{},{},{},{},{},{:.3}",
+        stats.seed,
+        stats.tiles,
+        kinds.join(","),
+        stats.open_cells,
+        stats.open_ratio,
+        stats.components,
+        stats.reachable_area,
+        stats.reachable_ratio,
+        stats.exit_reachable,
+        stats.generation_ms
+    )
+}
+
+pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    let config = cli
+        .config
+        .clone()
+        .unwrap_or_else(|| LevelConfig::for_level(cli.level));
+    if let Some(ref dir) = cli.svg {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let mut all_stats = vec![];
+    for seed in cli.first_seed..cli.first_seed + cli.count {
+        let (world, generation_time) = generate(&config, seed);
+        if let Some(ref dir) = cli.svg {
+            std::fs::write(dir.join(format!("{}.svg", seed)), world_to_svg(&world))?;
+        }
+        all_stats.push(WorldStats::measure(&world, seed, generation_time));
+        eprint!("\rGenerated {}/{}", all_stats.len(), cli.count);
+    }
+    eprintln!();
+
+    let mut output: Box<dyn Write> = match cli.output {
+        Some(ref path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    match cli.format {
+        Format::Csv => {
+            writeln!(output, "{}", csv_header())?;
+            for stats in all_stats.iter() {
+                writeln!(output, "{}", csv_row(stats))?;
+            }
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut output, &all_stats)?;
+            writeln!(output)?;
+        }
+    }
+    Ok(())
+}
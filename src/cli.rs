@@ -0,0 +1,171 @@
+use clap::Parser;
+use clap::error::ErrorKind;
+use rollroll::engine::game::Game;
+use rollroll::engine::level::LevelFile;
+use rollroll::engine::replay::Replay;
+use rollroll::engine::world::LevelConfig;
+use std::path::{Path, PathBuf};
+
+/* A level file given on the command line, along with where it came from so the editor can save
+ * back to it.
+ */
+#[derive(Clone)]
+pub struct LevelArg {
+    pub path: PathBuf,
+    pub file: LevelFile,
+}
+
+#[derive(Parser)]
+#[command(version, about = "Roll up stones and find the way out of the caves.")]
+pub struct Cli {
+    #[arg(
+        value_name = "LEVEL",
+        value_parser = parse_level,
+        conflicts_with_all = ["seed", "config", "replay"],
+        help = "Play this level file, .ron as text and anything else as binary, instead of a \
+                generated first level"
+    )]
+    pub level: Option<LevelArg>,
+
+    #[arg(
+        long,
+        conflicts_with = "replay",
+        help = "Seed the random number generator, so the same levels come up again"
+    )]
+    pub seed: Option<u64>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = parse_config,
+        conflicts_with = "replay",
+        help = "Generate the first level with the world config in this .ron file"
+    )]
+    pub config: Option<LevelConfig>,
+
+    #[arg(
+        long,
+        help = "Open a window of the given size instead of covering the whole screen"
+    )]
+    pub windowed: bool,
+
+    #[arg(
+        long,
+        default_value_t = 800,
+        requires = "windowed",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Width of the window in pixels"
+    )]
+    pub width: u32,
+
+    #[arg(
+        long,
+        default_value_t = 600,
+        requires = "windowed",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Height of the window in pixels"
+    )]
+    pub height: u32,
+
+    #[arg(
+        long,
+        help = "Wait for the display to refresh between frames instead of sleeping"
+    )]
+    pub vsync: bool,
+
+    #[arg(
+        long,
+        value_name = "SAMPLES",
+        default_value_t = 0,
+        value_parser = parse_samples,
+        help = "Smooth the edges of shapes with this many samples per pixel. Anything but the \
+                default of 0 switches to the OpenGL renderer, which multisampling needs"
+    )]
+    pub msaa: u8,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "replay",
+        help = "Record the movement of every tick to this file, to be played back with --replay"
+    )]
+    pub record: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = parse_replay,
+        help = "Play back a recording made with --record"
+    )]
+    pub replay: Option<Replay>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["windowed", "vsync", "msaa"],
+        help = "Run the game without a window, printing how it went at the end"
+    )]
+    pub headless: bool,
+
+    #[arg(
+        long,
+        requires = "headless",
+        help = "How many ticks to run for without a window. Defaults to the length of the replay"
+    )]
+    pub ticks: Option<usize>,
+}
+
+impl Cli {
+    /* Parses the arguments, exiting with a usage message when they do not make sense together. */
+    pub fn parse_and_validate() -> Self {
+        let cli = Self::parse();
+        if cli.headless && cli.ticks.is_none() && cli.replay.is_none() {
+            <Self as clap::CommandFactory>::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "--headless needs --ticks or --replay to know when to stop",
+                )
+                .exit();
+        }
+        cli
+    }
+
+    /* The game to start with. A replay brings its own game. */
+    pub fn game(&self) -> Result<Game, Box<dyn std::error::Error>> {
+        if let Some(ref replay) = self.replay {
+            return Ok(replay.start()?);
+        }
+        if let Some(ref level) = self.level {
+            return Ok(Game::load(0, &level.file)?);
+        }
+        let config = self
+            .config
+            .clone()
+            .unwrap_or_else(|| LevelConfig::for_level(0));
+        Ok(Game::new_with_config(
+            0,
+            config,
+            self.seed.unwrap_or_else(rand::random),
+        ))
+    }
+}
+
+fn parse_level(path: &str) -> Result<LevelArg, String> {
+    let path = PathBuf::from(path);
+    let file = LevelFile::load(&path).map_err(|e| e.to_string())?;
+    Ok(LevelArg { path, file })
+}
+
+fn parse_config(path: &str) -> Result<LevelConfig, String> {
+    LevelConfig::load(Path::new(path)).map_err(|e| e.to_string())
+}
+
+fn parse_samples(samples: &str) -> Result<u8, String> {
+    match samples.parse::<u8>() {
+        Ok(samples @ (0 | 2 | 4 | 8 | 16)) => Ok(samples),
+        _ => Err("expected one of 0, 2, 4, 8 or 16".to_string()),
+    }
+}
+
+fn parse_replay(path: &str) -> Result<Replay, String> {
+    Replay::load(Path::new(path)).map_err(|e| e.to_string())
+}
@@ -0,0 +1,279 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/* A handle to a value stored in an `Arena`. The generation is bumped every time a slot is reused,
+ * so a handle to a removed value never points at whatever got stored in its place.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Handle {
+    index: u32,
+    generation: u32,
+}
+
+impl Handle {
+    pub fn index(&self) -> usize {
+        self.index as usize
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl fmt::Display for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/* A generational arena. Handles are handed out by the arena that owns the values, so two arenas
+ * filled the same way hand out the same handles, and iteration always happens in slot order.
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedArena<T>")]
+pub struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ArenaError {
+    /* A free slot that is out of range, holds a value or is listed twice. */
+    BadFreeSlot(u32),
+    /* The number of free slots or values does not add up to the number of slots. */
+    WrongCount,
+}
+
+impl fmt::Display for ArenaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArenaError::BadFreeSlot(index) => write!(f, "Slot {} cannot be free", index),
+            ArenaError::WrongCount => write!(f, "The arena does not add up"),
+        }
+    }
+}
+
+impl std::error::Error for ArenaError {}
+
+/* An arena as it was read, before it is checked. Inserting into an arena whose free list points
+ * at a slot that does not exist would panic, and one pointing at a taken slot would overwrite it.
+ */
+#[derive(Deserialize)]
+struct UncheckedArena<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl<T> TryFrom<UncheckedArena<T>> for Arena<T> {
+    type Error = ArenaError;
+
+    fn try_from(arena: UncheckedArena<T>) -> Result<Self, Self::Error> {
+        let mut listed = vec![false; arena.slots.len()];
+        for index in arena.free.iter() {
+            let slot = arena.slots.get(*index as usize);
+            if slot.is_none_or(|slot| slot.value.is_some()) || listed[*index as usize] {
+                return Err(ArenaError::BadFreeSlot(*index));
+            }
+            listed[*index as usize] = true;
+        }
+        let values = arena
+            .slots
+            .iter()
+            .filter(|slot| slot.value.is_some())
+            .count();
+        if values != arena.len || values + arena.free.len() != arena.slots.len() {
+            return Err(ArenaError::WrongCount);
+        }
+        Ok(Self {
+            slots: arena.slots,
+            free: arena.free,
+            len: arena.len,
+        })
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Self {
+            slots: vec![],
+            free: vec![],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, value: T) -> Handle {
+        self.len += 1;
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.value = Some(value);
+                Handle {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                });
+                Handle {
+                    index: (self.slots.len() - 1) as u32,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        let slot = self.slots.get_mut(handle.index())?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        let value = slot.value.take()?;
+        slot.generation += 1;
+        self.free.push(handle.index);
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.get(handle).is_some()
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.slots
+            .get(handle.index())
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.value.as_ref())
+    }
+
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        self.slots
+            .get_mut(handle.index())
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.value.as_mut())
+    }
+
+    /* Borrows two different values mutably at the same time. */
+    pub fn get_pair_mut(&mut self, a: Handle, b: Handle) -> Option<(&mut T, &mut T)> {
+        if a.index == b.index || !self.contains(a) || !self.contains(b) {
+            return None;
+        }
+        let (low, high) = (a.index().min(b.index()), a.index().max(b.index()));
+        let (head, tail) = self.slots.split_at_mut(high);
+        let (low_value, high_value) = (head[low].value.as_mut()?, tail[0].value.as_mut()?);
+        match a.index < b.index {
+            true => Some((low_value, high_value)),
+            false => Some((high_value, low_value)),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &T)> {
+        self.slots.iter().enumerate().flat_map(|(index, slot)| {
+            slot.value.as_ref().map(|value| {
+                (
+                    Handle {
+                        index: index as u32,
+                        generation: slot.generation,
+                    },
+                    value,
+                )
+            })
+        })
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().flat_map(|slot| slot.value.as_ref())
+    }
+
+    /* Removes every value for which `keep` returns false, returning their handles. */
+    pub fn retain(&mut self, mut keep: impl FnMut(Handle, &T) -> bool) -> Vec<Handle> {
+        let removed: Vec<Handle> = self
+            .iter()
+            .filter(|(handle, value)| !keep(*handle, value))
+            .map(|(handle, _)| handle)
+            .collect();
+        removed.iter().for_each(|handle| {
+            self.remove(*handle);
+        });
+        removed
+    }
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stale_handles() {
+        let mut arena = Arena::new();
+        let first = arena.insert("first");
+        assert_eq!(arena.remove(first), Some("first"));
+
+        let second = arena.insert("second");
+        assert_eq!(first.index(), second.index());
+        assert_ne!(first, second);
+        assert_eq!(arena.get(first), None);
+        assert_eq!(arena.remove(first), None);
+        assert_eq!(arena.get(second), Some(&"second"));
+        assert_eq!(arena.len(), 1);
+    }
+
+    #[test]
+    fn test_deterministic_handles() {
+        let fill = || {
+            let mut arena = Arena::new();
+            let handles: Vec<Handle> = (0..5).map(|i| arena.insert(i)).collect();
+            arena.retain(|_, value| value % 2 == 0);
+            arena.insert(10);
+            (
+                handles,
+                arena.iter().map(|(h, v)| (h, *v)).collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(fill(), fill());
+    }
+
+    #[test]
+    fn test_corrupted_arenas_are_refused() {
+        let mut arena = Arena::new();
+        let handles: Vec<Handle> = (0..3).map(|i| arena.insert(i)).collect();
+        arena.remove(handles[1]);
+        let text = ron::to_string(&arena).unwrap();
+        let restored: Arena<i32> = ron::from_str(&text).unwrap();
+        assert_eq!(restored.values().collect::<Vec<_>>(), vec![&0, &2]);
+
+        for (corrupted, error) in [
+            ("free:[1]", "free:[7]"),
+            ("free:[1]", "free:[0]"),
+            ("free:[1]", "free:[1,1]"),
+            ("len:2", "len:3"),
+        ] {
+            let text = text.replace(corrupted, error);
+            assert!(ron::from_str::<Arena<i32>>(&text).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use crate::engine::game::{Command, Stroke};
+use glam::{Vec2, Vec3};
+
+/* Circles are drawn as polygons with this many sides per unit of radius, within bounds so tiny
+ * circles stay round and huge ones do not cost too much.
+ */
+const CIRCLE_SIDES_PER_UNIT: f32 = 200.;
+const MIN_CIRCLE_SIDES: usize = 8;
+const MAX_CIRCLE_SIDES: usize = 64;
+
+/* The commands of a frame turned into one list of coloured triangles, for backends that can draw
+ * them all in a single call. The buffers are kept between frames, like the command arena.
+ */
+#[derive(Default)]
+pub struct Batch {
+    clear: Vec3,
+    vertices: Vec<(Vec2, Vec3)>,
+    indices: Vec<u32>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /* The colour the frame starts out as. */
+    pub fn clear_colour(&self) -> Vec3 {
+        self.clear
+    }
+
+    /* Every corner of every triangle, in screen coordinates, along with its colour. */
+    pub fn vertices(&self) -> std::slice::Iter<'_, (Vec2, Vec3)> {
+        self.vertices.iter()
+    }
+
+    /* Three indices into the vertices per triangle. */
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /* Replaces the batch with the given commands. Everything the game draws is convex, so polygons
+     * are split into a fan of triangles around their first vertex. A clear throws away whatever
+     * was drawn before it.
+     */
+    pub fn fill(&mut self, commands: &[Command]) {
+        self.clear = Vec3::ZERO;
+        self.vertices.clear();
+        self.indices.clear();
+        for command in commands.iter() {
+            match command {
+                Command::Clear(colour) => {
+                    self.clear = *colour;
+                    self.vertices.clear();
+                    self.indices.clear();
+                }
+                Command::RenderCircle((center, radius, colour, stroke)) => {
+                    let sides = ((radius * CIRCLE_SIDES_PER_UNIT).ceil() as usize)
+                        .clamp(MIN_CIRCLE_SIDES, MAX_CIRCLE_SIDES);
+                    let step = std::f32::consts::TAU / sides as f32;
+                    let outline: Vec<Vec2> = (0..sides)
+                        .map(|s| center + Vec2::from_angle(s as f32 * step) * radius)
+                        .collect();
+                    self.push_shape(&outline, *colour, *stroke);
+                }
+                Command::RenderFilledPolygon((vertices, colour, stroke)) => {
+                    self.push_shape(vertices, *colour, *stroke);
+                }
+            }
+        }
+    }
+
+    fn push_shape(&mut self, outline: &[Vec2], colour: Vec3, stroke: Option<Stroke>) {
+        let first = self.vertices.len() as u32;
+        self.vertices.extend(outline.iter().map(|v| (*v, colour)));
+        let last = self.vertices.len() as u32;
+        for corner in first + 1..last.saturating_sub(1) {
+            self.indices.extend([first, corner, corner + 1]);
+        }
+        if let Some((stroke_colour, width)) = stroke {
+            self.push_ring(outline, stroke_colour, width);
+        }
+    }
+
+    /* A band of the given width along the inside of a convex outline, with mitred corners. The
+     * corners are never pushed in past the middle of the shape, so shapes thinner than the stroke
+     * end up filled with it instead of turning inside out.
+     */
+    fn push_ring(&mut self, outline: &[Vec2], colour: Vec3, width: f32) {
+        let count = outline.len();
+        if count < 3 {
+            return;
+        }
+        let middle = outline.iter().sum::<Vec2>() / count as f32;
+        let corner = |i: usize| outline[i % count];
+        /* Left of each edge is inside for counter clockwise outlines, and right for clockwise. */
+        let twice_area: f32 = (0..count).map(|i| corner(i).perp_dot(corner(i + 1))).sum();
+        let side = match twice_area >= 0. {
+            true => 1.,
+            false => -1.,
+        };
+        let inward = |a: Vec2, b: Vec2| (b - a).perp().normalize_or_zero() * side;
+
+        let first = self.vertices.len() as u32;
+        self.vertices.extend(outline.iter().map(|v| (*v, colour)));
+        for i in 0..count {
+            let here = corner(i);
+            let before = inward(corner(i + count - 1), here);
+            let after = inward(here, corner(i + 1));
+            let bisector = (before + after).normalize_or_zero();
+            let depth = match bisector.dot(after) > f32::EPSILON {
+                true => (width / bisector.dot(after)).min(here.distance(middle)),
+                false => 0.,
+            };
+            self.vertices.push((here + bisector * depth, colour));
+        }
+
+        let count = count as u32;
+        for i in 0..count {
+            let j = (i + 1) % count;
+            let (outer, next_outer) = (first + i, first + j);
+            let (inner, next_inner) = (first + count + i, first + count + j);
+            self.indices
+                .extend([outer, next_outer, next_inner, outer, next_inner, inner]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill() {
+        let square = vec![
+            Vec2::new(0., 0.),
+            Vec2::new(1., 0.),
+            Vec2::new(1., 1.),
+            Vec2::new(0., 1.),
+        ];
+        let mut batch = Batch::new();
+        batch.fill(&[
+            Command::RenderCircle((Vec2::ZERO, 1., Vec3::ONE, None)),
+            Command::Clear(Vec3::X),
+            Command::RenderFilledPolygon((square, Vec3::Y, None)),
+            Command::RenderCircle((Vec2::ZERO, 0.001, Vec3::Z, None)),
+        ]);
+
+        assert_eq!(batch.clear_colour(), Vec3::X);
+        assert_eq!(batch.vertices().len(), 4 + MIN_CIRCLE_SIDES);
+        assert_eq!(&batch.indices()[..6], &[0, 1, 2, 0, 2, 3]);
+        assert_eq!(batch.indices().len(), 3 * (2 + MIN_CIRCLE_SIDES - 2));
+        assert!(
+            batch
+                .vertices()
+                .skip(4)
+                .all(|(v, c)| (v.length() - 0.001).abs() < 1e-6 && *c == Vec3::Z)
+        );
+    }
+
+    #[test]
+    fn test_stroke() {
+        /* Clockwise, to check the stroke still goes on the inside. */
+        let square = vec![
+            Vec2::new(0., 0.),
+            Vec2::new(0., 1.),
+            Vec2::new(1., 1.),
+            Vec2::new(1., 0.),
+        ];
+        let mut batch = Batch::new();
+        batch.fill(&[Command::RenderFilledPolygon((
+            square,
+            Vec3::Y,
+            Some((Vec3::Z, 0.1)),
+        ))]);
+
+        assert_eq!(batch.vertices().len(), 4 + 8);
+        assert_eq!(batch.indices().len(), 6 + 4 * 6);
+        let inner: Vec<Vec2> = batch.vertices().skip(8).map(|(v, _)| *v).collect();
+        let expected = [
+            Vec2::new(0.1, 0.1),
+            Vec2::new(0.1, 0.9),
+            Vec2::new(0.9, 0.9),
+            Vec2::new(0.9, 0.1),
+        ];
+        for (corner, expected) in inner.iter().zip(expected) {
+            assert!(
+                corner.distance(expected) < 1e-5,
+                "{} != {}",
+                corner,
+                expected
+            );
+        }
+    }
+}
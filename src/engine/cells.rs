@@ -0,0 +1,410 @@
+use crate::engine::arena::Handle;
+use crate::engine::primitives::Polygon;
+use crate::engine::quadtree::QuadTree;
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
+
+/* Vertices are snapped to a grid this fine when looking for shared edges, to absorb floating
+ * point differences between neighbouring triangles.
+ */
+const SNAP: f32 = 1e-4;
+/* Points are looked up in the spatial index with a polygon this small around them. */
+const PROBE_SIZE: f32 = 1e-4;
+
+/* The open cells of a generated world: the triangles of the generation grid that ended up without
+ * a tile, with two cells being neighbours when they share an edge. Only the cells themselves are
+ * saved, everything else is worked out again from them.
+ */
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Polygon>", into = "Vec<Polygon>")]
+pub struct Cells {
+    cells: Vec<Polygon>,
+    neighbours: Vec<Vec<usize>>,
+    /* The cells again, by the handles the spatial index gave them, for finding the cell a point
+     * is in without checking every one of them.
+     */
+    index: Box<QuadTree>,
+    indexed: BTreeMap<Handle, usize>,
+}
+
+type SnappedVertex = (i64, i64);
+
+fn snap(v: Vec2) -> SnappedVertex {
+    ((v.x / SNAP).round() as i64, (v.y / SNAP).round() as i64)
+}
+
+impl Cells {
+    pub fn new(cells: Vec<Polygon>) -> Self {
+        let mut edges: HashMap<(SnappedVertex, SnappedVertex), Vec<usize>> = HashMap::new();
+        for (index, cell) in cells.iter().enumerate() {
+            for (start, end) in cell.edges() {
+                let (a, b) = (snap(*start), snap(*end));
+                edges.entry((a.min(b), a.max(b))).or_default().push(index);
+            }
+        }
+
+        let mut neighbours = vec![vec![]; cells.len()];
+        for sharing in edges.values() {
+            for a in sharing.iter() {
+                for b in sharing.iter().filter(|b| *b != a) {
+                    neighbours[*a].push(*b);
+                }
+            }
+        }
+        neighbours.iter_mut().for_each(|n| {
+            n.sort_unstable();
+            n.dedup();
+        });
+
+        let mut index = Box::new(QuadTree::new());
+        let mut indexed = BTreeMap::new();
+        for (cell, polygon) in cells.iter().enumerate() {
+            for handle in index.insert(polygon.clone()) {
+                indexed.insert(handle, cell);
+            }
+        }
+
+        Self {
+            cells,
+            neighbours,
+            index,
+            indexed,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Polygon> {
+        self.cells.get(index)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Polygon> {
+        self.cells.iter()
+    }
+
+    pub fn neighbours(&self, index: usize) -> &[usize] {
+        &self.neighbours[index]
+    }
+
+    /* The first cell containing the point, if any. */
+    pub fn cell_at(&self, point: Vec2) -> Option<usize> {
+        let probe = Polygon::new_regular(6, PROBE_SIZE, point, 0.);
+        self.index
+            .find_handles_in_area(&probe)
+            .flat_map(|handle| self.indexed.get(&handle).copied())
+            .filter(|cell| self.cells[*cell].contains_point(point))
+            .min()
+    }
+
+    /* Breadth first search from the cell containing `from`, giving the number of steps from it to
+     * every cell, or `None` for cells that cannot be reached.
+     */
+    pub fn distance_field(&self, from: Vec2) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.cells.len()];
+        let Some(start) = self.cell_at(from) else {
+            return distances;
+        };
+
+        distances[start] = Some(0);
+        let mut queue = VecDeque::from([start]);
+        while let Some(current) = queue.pop_front() {
+            let distance = distances[current].unwrap() + 1;
+            for next in self.neighbours[current].iter() {
+                if distances[*next].is_none() {
+                    distances[*next] = Some(distance);
+                    queue.push_back(*next);
+                }
+            }
+        }
+        distances
+    }
+
+    /* Labels every cell with the group of connected cells it belongs to. Groups are numbered from
+     * zero in the order of their first cell. Returns the labels and the number of groups.
+     */
+    pub fn components(&self) -> (Vec<usize>, usize) {
+        let mut labels = vec![usize::MAX; self.cells.len()];
+        let mut count = 0;
+        for start in 0..self.cells.len() {
+            if labels[start] != usize::MAX {
+                continue;
+            }
+            labels[start] = count;
+            let mut queue = VecDeque::from([start]);
+            while let Some(current) = queue.pop_front() {
+                for next in self.neighbours[current].iter() {
+                    if labels[*next] == usize::MAX {
+                        labels[*next] = count;
+                        queue.push_back(*next);
+                    }
+                }
+            }
+            count += 1;
+        }
+        (labels, count)
+    }
+}
+
+impl From<Vec<Polygon>> for Cells {
+    fn from(cells: Vec<Polygon>) -> Self {
+        Self::new(cells)
+    }
+}
+
+impl From<Cells> for Vec<Polygon> {
+    fn from(cells: Cells) -> Self {
+        cells.cells
+    }
+}
+
+impl Cells {
+    /* For every cell, the neighbouring cell to move to in order to get closer to `target`. The
+     * cell containing the target, and cells the target cannot be reached from, have `None`. This is
+     * cheaper than a path per enemy when many of them chase the same thing.
+     */
+    pub fn flow_field(&self, target: Vec2) -> Vec<Option<usize>> {
+        let distances = self.distance_field(target);
+        (0..self.cells.len())
+            .map(|cell| {
+                let own = distances[cell]?;
+                self.neighbours[cell]
+                    .iter()
+                    .filter_map(|n| distances[*n].map(|d| (d, *n)))
+                    .min()
+                    .filter(|(d, _)| *d < own)
+                    .map(|(_, n)| n)
+            })
+            .collect()
+    }
+
+    /* A* search for the shortest path of cells between the cells containing `from` and `to`,
+     * measuring distances between cell centroids.
+     */
+    pub fn path(&self, from: Vec2, to: Vec2) -> Option<Vec<usize>> {
+        let start = self.cell_at(from)?;
+        let goal = self.cell_at(to)?;
+        let centroids: Vec<Vec2> = self.cells.iter().map(|c| c.centroid()).collect();
+        let heuristic = |cell: usize| centroids[cell].distance(centroids[goal]);
+        /* The heap needs a total order, so costs are kept as integer multiples of a small unit. */
+        let key = |cost: f32| Reverse((cost * 1e6) as u64);
+
+        let mut costs = vec![f32::INFINITY; self.cells.len()];
+        let mut came_from = vec![None; self.cells.len()];
+        let mut open = BinaryHeap::from([(key(heuristic(start)), start)]);
+        costs[start] = 0.;
+
+        while let Some((_, current)) = open.pop() {
+            if current == goal {
+                let mut path = vec![goal];
+                while let Some(previous) = came_from[*path.last().unwrap()] {
+                    path.push(previous);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for next in self.neighbours[current].iter() {
+                let cost = costs[current] + centroids[current].distance(centroids[*next]);
+                if cost < costs[*next] {
+                    costs[*next] = cost;
+                    came_from[*next] = Some(current);
+                    open.push((key(cost + heuristic(*next)), *next));
+                }
+            }
+        }
+        None
+    }
+
+    /* The edge shared by two neighbouring cells, as the points on the left and right when crossing
+     * it from `from` into `to`. Cells are wound counter clockwise, so walking out through one of
+     * their edges has its end on the left and its start on the right.
+     */
+    pub fn portal(&self, from: usize, to: usize) -> Option<(Vec2, Vec2)> {
+        let shared: Vec<SnappedVertex> = self.cells.get(to)?.vertices().map(|v| snap(*v)).collect();
+        self.cells
+            .get(from)?
+            .edges()
+            .find(|(start, end)| shared.contains(&snap(*start)) && shared.contains(&snap(*end)))
+            .map(|(start, end)| (*end, *start))
+    }
+
+    /* Straightens a path of cells from `from` to `to` into as few waypoints as possible using the
+     * funnel algorithm. The funnel is narrowed portal by portal, and whenever one side of it would
+     * cross over the other, the corner on that side becomes a waypoint and the new apex.
+     */
+    pub fn funnel(&self, from: Vec2, to: Vec2, path: &[usize]) -> Vec<Vec2> {
+        let mut portals = vec![(from, from)];
+        portals.extend(
+            path.windows(2)
+                .filter_map(|pair| self.portal(pair[0], pair[1])),
+        );
+        portals.push((to, to));
+
+        /* Which side of the ray from `apex` through `a` the point `b` is on, positive being the
+         * left. Neighbouring cells do not always agree on their shared vertices down to the last
+         * bit, so anything within snapping distance of the ray counts as on it.
+         */
+        let side = |apex: Vec2, a: Vec2, b: Vec2| match (a - apex).perp_dot(b - apex) {
+            side if side.abs() < SNAP * SNAP => 0.,
+            side => side,
+        };
+        let same = |a: Vec2, b: Vec2| snap(a) == snap(b);
+
+        let mut waypoints = vec![from];
+        let (mut apex, mut left, mut right) = (from, from, from);
+        let (mut left_index, mut right_index) = (0, 0);
+        let mut index = 1;
+        while index < portals.len() {
+            let (next_left, next_right) = portals[index];
+
+            if side(apex, right, next_right) >= 0. {
+                if same(apex, right) || side(apex, left, next_right) < 0. {
+                    (right, right_index) = (next_right, index);
+                } else {
+                    waypoints.push(left);
+                    (apex, right, right_index) = (left, left, left_index);
+                    index = left_index + 1;
+                    continue;
+                }
+            }
+
+            if side(apex, left, next_left) <= 0. {
+                if same(apex, left) || side(apex, right, next_left) > 0. {
+                    (left, left_index) = (next_left, index);
+                } else {
+                    waypoints.push(right);
+                    (apex, left, left_index) = (right, right, right_index);
+                    index = right_index + 1;
+                    continue;
+                }
+            }
+
+            index += 1;
+        }
+
+        waypoints.push(to);
+        waypoints.dedup_by(|a, b| same(*a, *b));
+        waypoints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_field() {
+        /* A strip of alternating triangles, like a row of the world grid, and one triangle off on
+         * its own.
+         */
+        let mut triangles: Vec<Polygon> = (0..6)
+            .map(|i| {
+                let rotation = (i % 2) as f32 * std::f32::consts::PI;
+                Polygon::new_triangle(1., Vec2::new(i as f32 * 0.5, 0.), rotation)
+            })
+            .collect();
+        triangles.push(Polygon::new_triangle(1., Vec2::new(10., 0.), 0.));
+        let cells = Cells::new(triangles);
+
+        assert_eq!(cells.neighbours(0), &[1]);
+        assert_eq!(cells.neighbours(3), &[2, 4]);
+
+        let distances = cells.distance_field(Vec2::ZERO);
+        assert_eq!(
+            distances,
+            vec![Some(0), Some(1), Some(2), Some(3), Some(4), Some(5), None]
+        );
+        assert_eq!(cells.components(), (vec![0, 0, 0, 0, 0, 0, 1], 2));
+    }
+
+    #[test]
+    fn test_cell_at() {
+        /* Enough rows of triangles for the spatial index to split a few times. */
+        let triangles: Vec<Polygon> = (0..20)
+            .flat_map(|row| {
+                (0..20).map(move |column| {
+                    let rotation = ((row + column) % 2) as f32 * std::f32::consts::PI;
+                    let center = Vec2::new(column as f32 * 0.5, row as f32 * 0.866);
+                    Polygon::new_triangle(1., center, rotation)
+                })
+            })
+            .collect();
+        let cells = Cells::new(triangles.clone());
+        for x in -4..44 {
+            for y in -4..72 {
+                let point = Vec2::new(x as f32 * 0.25 + 0.013, y as f32 * 0.25 + 0.017);
+                let expected = triangles.iter().position(|t| t.contains_point(point));
+                assert_eq!(cells.cell_at(point), expected, "at {}", point);
+            }
+        }
+    }
+
+    #[test]
+    fn test_paths() {
+        let mut triangles: Vec<Polygon> = (0..6)
+            .map(|i| {
+                let rotation = (i % 2) as f32 * std::f32::consts::PI;
+                Polygon::new_triangle(1., Vec2::new(i as f32 * 0.5, 0.), rotation)
+            })
+            .collect();
+        triangles.push(Polygon::new_triangle(1., Vec2::new(10., 0.), 0.));
+        let cells = Cells::new(triangles);
+        let end = cells.get(5).unwrap().centroid();
+
+        assert_eq!(cells.path(Vec2::ZERO, end), Some(vec![0, 1, 2, 3, 4, 5]));
+        assert_eq!(cells.path(Vec2::ZERO, Vec2::new(10., 0.)), None);
+
+        let flow = cells.flow_field(end);
+        assert_eq!(
+            flow,
+            vec![Some(1), Some(2), Some(3), Some(4), Some(5), None, None]
+        );
+    }
+
+    #[test]
+    fn test_funnel() {
+        /* A strip of triangles bending upwards halfway, so the shortest way through it has to cut
+         * around the inside corner.
+         */
+        let size = 1.;
+        let height = size * 3_f32.sqrt() / 2.;
+        let mut triangles: Vec<Polygon> = (0..6)
+            .map(|i| {
+                let rotation = (i % 2) as f32 * std::f32::consts::PI;
+                Polygon::new_triangle(size, Vec2::new(i as f32 * 0.5, 0.), rotation)
+            })
+            .collect();
+        triangles.extend((0..4).map(|i| {
+            let rotation = (i % 2) as f32 * std::f32::consts::PI;
+            Polygon::new_triangle(size, Vec2::new(2.5 + i as f32 * 0.5, height), rotation)
+        }));
+        let cells = Cells::new(triangles);
+
+        let straight_end = cells.get(4).unwrap().centroid();
+        let path = cells.path(Vec2::ZERO, straight_end).unwrap();
+        assert_eq!(
+            cells.funnel(Vec2::ZERO, straight_end, &path),
+            vec![Vec2::ZERO, straight_end]
+        );
+
+        let start = cells.get(0).unwrap().centroid();
+        let end = cells.get(9).unwrap().centroid();
+        let path = cells.path(start, end).unwrap();
+        let waypoints = cells.funnel(start, end, &path);
+        assert_eq!(waypoints.first(), Some(&start));
+        assert_eq!(waypoints.last(), Some(&end));
+        assert!(waypoints.len() > 2 && waypoints.len() < path.len());
+        for pair in waypoints.windows(2) {
+            /* Paths hug the corners, so they can run right along the edges of the cells. */
+            let probe = Polygon::new_regular(6, 1e-4, pair[0].midpoint(pair[1]), 0.);
+            assert!(cells.iter().any(|cell| cell.collides_with(&probe)));
+        }
+    }
+}
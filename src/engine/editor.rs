@@ -0,0 +1,332 @@
+use crate::engine::arena::Handle;
+use crate::engine::cells::Cells;
+use crate::engine::entities::COLLECTIBLE_COLOUR;
+use crate::engine::game::{Command, EXIT_UNLOCKED_COLOUR, outline};
+use crate::engine::level::{LevelError, LevelFile};
+use crate::engine::primitives::Polygon;
+use crate::engine::svg::{CELL_COLOUR, MARKER_RADIUS, SPAWN_COLOUR};
+use crate::engine::world::{Lattice, LevelConfig, TileKind, World};
+use glam::{Vec2, Vec3};
+
+/* Clicks are looked up in the world with a polygon this small around them. */
+const PROBE_SIZE: f32 = 1e-3;
+/* Lattice triangles are shrunk by this fraction before checking them against the tiles, so
+ * tiles merely touching them do not close them off.
+ */
+const CELL_MARGIN: f32 = 0.1;
+const MIN_ZOOM: f32 = 0.1;
+const MAX_ZOOM: f32 = 10.;
+const CURSOR_COLOUR: Vec3 = Vec3::new(0.5, 0.5, 0.5);
+
+/* What a click in the editor does. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tool {
+    Tile(TileKind),
+    Erase,
+    Spawn,
+    Exit,
+    Collectible,
+}
+
+/* Builds levels by hand. Tiles are placed on a lattice of equilateral triangles like the one the
+ * generator makes, and the open triangles around them become the navigation mesh. Everything is
+ * kept in a `World`, so its spatial index is what clicks are picked with and its level file
+ * format is what the result is saved as.
+ */
+pub struct Editor {
+    world: World,
+    lattice: Lattice,
+    /* The exit is kept as a point, as the cell it falls in changes as tiles are placed. */
+    exit: Option<Vec2>,
+    tool: Tool,
+    camera: Vec2,
+    zoom: f32,
+}
+
+impl Editor {
+    /* Starts an empty level, on the lattice the generator would use for the given config. */
+    pub fn new(config: &LevelConfig) -> Self {
+        let mut editor = Self {
+            world: World::default(),
+            lattice: Lattice::for_config(config),
+            exit: None,
+            tool: Tool::Tile(TileKind::Solid),
+            camera: Vec2::ZERO,
+            zoom: 1.,
+        };
+        editor.rebuild_cells();
+        editor
+    }
+
+    /* Opens a level for editing. Its cells are kept as they are until the first change. The
+     * lattice is lined up with the first triangle found among its tiles and cells, so new tiles
+     * fit between the ones already there. `tile_size` is only used when it has no triangles.
+     */
+    pub fn from_level(level: &LevelFile, tile_size: f32) -> Result<Self, LevelError> {
+        let world = World::from_level(level)?;
+        let lattice = world
+            .iter()
+            .chain(world.cells().iter())
+            .find_map(Lattice::of)
+            .unwrap_or(Lattice {
+                tile_size,
+                origin: Vec2::ZERO,
+            });
+        Ok(Self {
+            lattice,
+            exit: world.exit().map(|exit| exit.centroid()),
+            camera: world.spawn(),
+            world,
+            tool: Tool::Tile(TileKind::Solid),
+            zoom: 1.,
+        })
+    }
+
+    pub fn to_level(&self) -> LevelFile {
+        self.world.to_level()
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn tool(&self) -> Tool {
+        self.tool
+    }
+
+    pub fn set_tool(&mut self, tool: Tool) {
+        self.tool = tool;
+    }
+
+    pub fn pan(&mut self, offset: Vec2) {
+        self.camera += offset / self.zoom;
+    }
+
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    /* Turns a point on the screen, in the coordinates commands are given in, into the world. */
+    pub fn screen_to_world(&self, point: Vec2) -> Vec2 {
+        point / self.zoom + self.camera
+    }
+
+    /* Uses the current tool at the given point in the world. */
+    pub fn apply(&mut self, point: Vec2) {
+        match self.tool {
+            Tool::Tile(kind) => {
+                let triangle = self.lattice.triangle_at(point);
+                if !self.overlaps_tiles(&triangle) {
+                    self.world.add_tile(triangle, kind);
+                    self.rebuild_cells();
+                }
+            }
+            Tool::Erase => self.erase(point),
+            Tool::Spawn => self.world.set_spawn(point),
+            Tool::Exit => {
+                self.exit = Some(point);
+                self.rebuild_cells();
+            }
+            Tool::Collectible => self.world.add_collectible_spawn(point),
+        }
+    }
+
+    /* Takes out the tile, collectibles and exit at the given point, whatever the current tool. */
+    pub fn erase(&mut self, point: Vec2) {
+        let marker = Polygon::new_regular(8, MARKER_RADIUS, point, 0.);
+        self.world.remove_collectible_spawns(&marker);
+        if self.exit.is_some_and(|exit| marker.contains_point(exit)) {
+            self.exit = None;
+        }
+        if let Some(handle) = self.tile_at(point) {
+            self.world.break_tile(handle);
+        }
+        self.rebuild_cells();
+    }
+
+    pub fn render(&self, cursor: Vec2, command_arena: &mut Vec<Command>) {
+        use Command::*;
+
+        let camera = self.camera;
+        let zoom = self.zoom;
+        let screen = |polygon: &Polygon| {
+            polygon
+                .vertices()
+                .map(|v| (v - camera) * zoom)
+                .collect::<Vec<Vec2>>()
+        };
+
+        command_arena.clear();
+        command_arena.push(Clear(Vec3::ZERO));
+        for cell in self.world.cells().iter() {
+            command_arena.push(RenderFilledPolygon((screen(cell), CELL_COLOUR, None)));
+        }
+        if let Some(exit) = self.world.exit() {
+            command_arena.push(RenderFilledPolygon((
+                screen(exit),
+                EXIT_UNLOCKED_COLOUR,
+                None,
+            )));
+        }
+        for (tile, kind) in self.world.tiles() {
+            command_arena.push(RenderFilledPolygon((
+                screen(tile),
+                kind.colour(),
+                outline(kind.colour()),
+            )));
+        }
+        for spot in self.world.collectible_spawns() {
+            command_arena.push(RenderCircle((
+                (spot - camera) * zoom,
+                MARKER_RADIUS * zoom,
+                COLLECTIBLE_COLOUR,
+                None,
+            )));
+        }
+        command_arena.push(RenderCircle((
+            (self.world.spawn() - camera) * zoom,
+            MARKER_RADIUS * zoom,
+            SPAWN_COLOUR,
+            None,
+        )));
+
+        /* Shows where a tile would go before it is placed. */
+        let point = self.screen_to_world(cursor);
+        if let Tool::Tile(kind) = self.tool {
+            command_arena.push(RenderFilledPolygon((
+                screen(&self.lattice.triangle_at(point)),
+                kind.colour() * 0.5,
+                None,
+            )));
+        } else {
+            command_arena.push(RenderCircle((cursor, PROBE_SIZE * 4., CURSOR_COLOUR, None)));
+        }
+    }
+
+    fn tile_at(&self, point: Vec2) -> Option<Handle> {
+        let probe = Polygon::new_regular(6, PROBE_SIZE, point, 0.);
+        self.world
+            .find_tiles_in_area(&probe)
+            .find(|(_, tile, _)| tile.contains_point(point))
+            .map(|(handle, _, _)| handle)
+    }
+
+    /* Whether a tile is already in the way of the given triangle. It is shrunk a little first, so
+     * the tiles next to it on the lattice do not count.
+     */
+    fn overlaps_tiles(&self, triangle: &Polygon) -> bool {
+        let mut shrunk = triangle.clone();
+        shrunk.scale(1. - CELL_MARGIN);
+        self.world.find_in_area(&shrunk).next().is_some()
+    }
+
+    /* The cells are the open lattice triangles within one tile of everything placed so far. */
+    fn rebuild_cells(&mut self) {
+        let points = self
+            .world
+            .iter()
+            .flat_map(|tile| {
+                let (min, max) = tile.aabb();
+                [min, max]
+            })
+            .chain(self.world.collectible_spawns().copied())
+            .chain(self.exit)
+            .chain([self.world.spawn()]);
+        let (min, max) = points.fold((Vec2::MAX, Vec2::MIN), |(min, max), point| {
+            (min.min(point), max.max(point))
+        });
+        let min = (min - Vec2::splat(self.lattice.tile_size) - self.lattice.origin)
+            / self.lattice.spacing();
+        let max = (max + Vec2::splat(self.lattice.tile_size) - self.lattice.origin)
+            / self.lattice.spacing();
+
+        let mut open = vec![];
+        for row in min.y.floor() as i32..=max.y.ceil() as i32 {
+            for column in min.x.floor() as i32..=max.x.ceil() as i32 {
+                let triangle = self.lattice.triangle(column, row);
+                if !self.overlaps_tiles(&triangle) {
+                    open.push(triangle);
+                }
+            }
+        }
+
+        let cells = Cells::new(open);
+        let exit = self.exit.and_then(|exit| cells.cell_at(exit));
+        self.world.set_cells(cells, exit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::world::GeneratorResult;
+
+    #[test]
+    fn test_edited_level_plays() {
+        let mut editor = Editor::new(&LevelConfig::for_level(0));
+        for x in -4..=8 {
+            editor.apply(Vec2::new(x as f32 * 0.1, 0.35));
+            editor.apply(Vec2::new(x as f32 * 0.1, -0.35));
+        }
+        editor.set_tool(Tool::Spawn);
+        editor.apply(Vec2::new(0., 0.));
+        editor.set_tool(Tool::Exit);
+        editor.apply(Vec2::new(0.6, 0.));
+        editor.set_tool(Tool::Collectible);
+        editor.apply(Vec2::new(0.3, 0.));
+
+        let level = editor.to_level();
+        let world = World::from_level(&level).unwrap();
+        assert_eq!(world.tiles().count(), 26);
+        assert_eq!(world.collectible_spawns().count(), 1);
+        assert!(world.exit().unwrap().contains_point(Vec2::new(0.6, 0.)));
+        assert!(world.path(world.spawn(), Vec2::new(0.6, 0.)).is_some());
+    }
+
+    #[test]
+    fn test_erase() {
+        let mut editor = Editor::new(&LevelConfig::for_level(0));
+        editor.apply(Vec2::new(0.5, 0.5));
+        editor.apply(Vec2::new(0.5, 0.5));
+        assert_eq!(editor.world().tiles().count(), 1);
+
+        editor.set_tool(Tool::Collectible);
+        editor.apply(Vec2::new(0.5, 0.5));
+        editor.erase(Vec2::new(0.5, 0.5));
+        assert_eq!(editor.world().tiles().count(), 0);
+        assert_eq!(editor.world().collectible_spawns().count(), 0);
+    }
+
+    #[test]
+    fn test_generated_level_keeps_its_lattice() {
+        let mut generator = World::generator(LevelConfig::for_level(1), 2);
+        let world = loop {
+            match generator.generate(std::time::Duration::from_millis(100)) {
+                GeneratorResult::Generating(next) => generator = next,
+                GeneratorResult::Done(world) => break world,
+            }
+        };
+        let level = world.to_level();
+        let mut editor = Editor::from_level(&level, 1.).unwrap();
+        assert!((editor.lattice.tile_size - 0.2).abs() < 1e-5);
+
+        /* Placing the exit again builds the cells anew, which should land where they were. */
+        let exit = world.exit().unwrap().centroid();
+        editor.set_tool(Tool::Exit);
+        editor.apply(exit);
+        let cells = editor.world().cells();
+        assert!(cells.len() >= world.cells().len());
+        for cell in world.cells().iter() {
+            let rebuilt = cells.get(cells.cell_at(cell.centroid()).unwrap()).unwrap();
+            assert!(rebuilt.centroid().distance(cell.centroid()) < 1e-4);
+        }
+
+        /* Every tile is already on the lattice, so none can be placed on top of another. */
+        let tiles = editor.world().tiles().count();
+        editor.set_tool(Tool::Tile(TileKind::Solid));
+        for tile in world.iter() {
+            editor.apply(tile.centroid());
+        }
+        assert_eq!(editor.world().tiles().count(), tiles);
+    }
+}
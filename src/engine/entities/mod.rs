@@ -0,0 +1,209 @@
+mod player;
+pub mod systems;
+
+pub use player::{Material, Player, Stone};
+
+use crate::engine::arena::{Arena, Handle};
+use crate::engine::primitives::Polygon;
+use crate::engine::quadtree::QuadTree;
+use glam::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/* Game objects other than the player are entities: plain handles with whatever components are
+ * attached to them. Each kind of component lives in its own map keyed by entity, and systems (see
+ * `systems`) walk the maps they care about. The maps are ordered so systems always visit entities
+ * in the same order.
+ */
+pub type Entity = Handle;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Transform {
+    pub position: Vec2,
+    pub rotation: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Velocity(pub Vec2);
+
+/* The outline of an entity, relative to its transform. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Shape(pub Polygon);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RenderStyle {
+    pub colour: Vec3,
+}
+
+/* A hostile roller. It steers towards the player at up to `max_speed`, and after hitting the
+ * player it backs off for `cooldown` ticks.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Enemy {
+    pub max_speed: f32,
+    pub max_force: f32,
+    pub cooldown: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Pickup {
+    Available,
+    Collected,
+}
+
+const COLLECTIBLE_SIZE: f32 = 0.03;
+pub const COLLECTIBLE_COLOUR: Vec3 = Vec3::new(1., 0.85, 0.2);
+const ENEMY_SIZE: f32 = 0.04;
+pub const ENEMY_COLOUR: Vec3 = Vec3::new(0.9, 0.15, 0.1);
+const ENEMY_MAX_SPEED: f32 = 0.012;
+const ENEMY_MAX_FORCE: f32 = 0.0008;
+
+/* Pickups are also kept in a spatial index, so finding the ones the player touches does not mean
+ * checking every single one. The index maps its own handles back to entities.
+ */
+#[derive(Default, Serialize, Deserialize)]
+pub struct Entities {
+    alive: Arena<()>,
+    pub transforms: BTreeMap<Entity, Transform>,
+    pub velocities: BTreeMap<Entity, Velocity>,
+    pub shapes: BTreeMap<Entity, Shape>,
+    pub render_styles: BTreeMap<Entity, RenderStyle>,
+    pub pickups: BTreeMap<Entity, Pickup>,
+    pub enemies: BTreeMap<Entity, Enemy>,
+    pickup_index: QuadTree,
+    indexed: BTreeMap<Entity, Vec<Handle>>,
+    indexed_entities: BTreeMap<Handle, Entity>,
+}
+
+impl Entities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.alive.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.alive.is_empty()
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        self.alive.insert(())
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.alive.contains(entity)
+    }
+
+    /* A collectible is a pickup that sits still until the player rolls over it. */
+    pub fn spawn_collectible(&mut self, position: Vec2) -> Entity {
+        let entity = self.spawn();
+        self.transforms.insert(
+            entity,
+            Transform {
+                position,
+                rotation: 0.,
+            },
+        );
+        self.shapes.insert(
+            entity,
+            Shape(Polygon::new_regular(5, COLLECTIBLE_SIZE, Vec2::ZERO, 0.)),
+        );
+        self.render_styles.insert(
+            entity,
+            RenderStyle {
+                colour: COLLECTIBLE_COLOUR,
+            },
+        );
+        self.pickups.insert(entity, Pickup::Available);
+        self.index_pickup(entity);
+        entity
+    }
+
+    pub fn spawn_enemy(&mut self, position: Vec2) -> Entity {
+        let entity = self.spawn();
+        self.transforms.insert(
+            entity,
+            Transform {
+                position,
+                rotation: 0.,
+            },
+        );
+        self.velocities.insert(entity, Velocity(Vec2::ZERO));
+        self.shapes.insert(
+            entity,
+            Shape(Polygon::new_regular(8, ENEMY_SIZE, Vec2::ZERO, 0.)),
+        );
+        self.render_styles.insert(
+            entity,
+            RenderStyle {
+                colour: ENEMY_COLOUR,
+            },
+        );
+        self.enemies.insert(
+            entity,
+            Enemy {
+                max_speed: ENEMY_MAX_SPEED,
+                max_force: ENEMY_MAX_FORCE,
+                cooldown: 0,
+            },
+        );
+        entity
+    }
+
+    /* Puts the current shape of a pickup into the spatial index. This needs to be called again
+     * whenever the pickup moves, which the movement system takes care of.
+     */
+    pub fn index_pickup(&mut self, entity: Entity) {
+        self.unindex_pickup(entity);
+        if let Some(shape) = self.world_shape(entity) {
+            let handles = self.pickup_index.insert(shape);
+            handles.iter().for_each(|handle| {
+                self.indexed_entities.insert(*handle, entity);
+            });
+            self.indexed.insert(entity, handles);
+        }
+    }
+
+    fn unindex_pickup(&mut self, entity: Entity) {
+        for handle in self.indexed.remove(&entity).unwrap_or_default() {
+            self.pickup_index.remove(handle);
+            self.indexed_entities.remove(&handle);
+        }
+    }
+
+    /* The pickups whose shapes touch the given area, whether they were collected or not. */
+    pub fn pickups_in_area(&self, area: &Polygon) -> Vec<Entity> {
+        let mut found: Vec<Entity> = self
+            .pickup_index
+            .find_handles_in_area(area)
+            .flat_map(|handle| self.indexed_entities.get(&handle).copied())
+            .collect();
+        found.sort();
+        found.dedup();
+        found
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        if self.alive.remove(entity).is_some() {
+            self.unindex_pickup(entity);
+            self.transforms.remove(&entity);
+            self.velocities.remove(&entity);
+            self.shapes.remove(&entity);
+            self.render_styles.remove(&entity);
+            self.pickups.remove(&entity);
+            self.enemies.remove(&entity);
+        }
+    }
+
+    /* The shape of an entity placed where its transform says it is. */
+    pub fn world_shape(&self, entity: Entity) -> Option<Polygon> {
+        let transform = self.transforms.get(&entity)?;
+        let Shape(shape) = self.shapes.get(&entity)?;
+        let mut shape = shape.clone();
+        shape.rotate_around(transform.rotation, Vec2::ZERO);
+        shape.translate(transform.position);
+        Some(shape)
+    }
+}
@@ -0,0 +1,453 @@
+use crate::engine::primitives::Polygon;
+use glam::{Quat, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+
+/* Top speed is proportional to the radius, so a bigger ball covers more ground, and the heavier
+ * the ball the more ticks it takes to get up to that speed. A ball of the reference mass reaches
+ * top speed in `ACCELERATION_TICKS` ticks.
+ */
+const SPEED_PER_RADIUS: f32 = 0.5;
+const ACCELERATION_TICKS: f32 = 20.;
+const REFERENCE_MASS: f32 = 12.;
+/* How much speed the ball loses every tick when nothing pushes it. */
+const ROLLING_RESISTANCE: f32 = 0.0002;
+/* The golden angle, pi * (3 - sqrt(5)), used to spread stones over each layer of the ball. */
+const GOLDEN_ANGLE: f32 = 2.399_963_2;
+const STONE_SIZE: f32 = 0.01;
+/* Stones are packed in concentric layers this far apart. Layer `k` has room for
+ * `STONES_PER_LAYER * k * k` stones, which keeps the density roughly constant since the area of a
+ * layer grows with the square of its radius.
+ */
+const LAYER_SPACING: f32 = 0.03;
+const STONES_PER_LAYER: usize = 12;
+const COLLISION_SIDES: usize = 12;
+/* The camera shows the whole ball at this radius, and zooms out as the ball grows past it. */
+const REFERENCE_RADIUS: f32 = 0.04;
+
+/* What a stone is made of. Weight adds to the mass of the ball, and stickiness is how firmly the
+ * stone holds on to the ball, from 0 (falls off at the slightest bump) to 1 (never comes off).
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Material {
+    pub weight: f32,
+    pub stickiness: f32,
+}
+
+impl Material {
+    pub const ROCK: Material = Material {
+        weight: 1.,
+        stickiness: 0.5,
+    };
+}
+
+/* A single piece of the ball. The position is relative to the center of the ball, and the shape
+ * is a regular polygon with the given number of sides and size (the distance from its center to
+ * its vertices).
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stone {
+    position: Vec3,
+    slot: usize,
+    sides: usize,
+    size: f32,
+    colour: Vec3,
+    material: Material,
+}
+
+impl Stone {
+    pub fn new(sides: usize, size: f32, colour: Vec3, material: Material) -> Self {
+        Self {
+            position: Vec3::ZERO,
+            slot: 0,
+            sides,
+            size,
+            colour,
+            material,
+        }
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn sides(&self) -> usize {
+        self.sides
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    pub fn colour(&self) -> Vec3 {
+        self.colour
+    }
+
+    pub fn material(&self) -> Material {
+        self.material
+    }
+
+    /* The stone as seen by the camera, relative to the center of the ball. */
+    pub fn polygon(&self) -> Polygon {
+        Polygon::new_regular(self.sides, self.size, self.position.truncate(), 0.)
+    }
+}
+
+impl Default for Stone {
+    fn default() -> Self {
+        Stone::new(6, STONE_SIZE, Vec3::ONE, Material::ROCK)
+    }
+}
+
+/* The ball rolls without slipping on the ground plane, with the camera looking down the z axis.
+ * Its motion is stored as an angular velocity (radians per tick), and the linear velocity follows
+ * from it and the radius of the ball.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct Player {
+    stones: Vec<Stone>,
+    orientation: Quat,
+    position: Vec2,
+    angular_velocity: Vec3,
+}
+
+/* Returns the layer a slot is in, and the index of the slot within that layer. Slot 0 is the
+ * center of the ball, on its own in layer 0.
+ */
+fn slot_layer(slot: usize) -> (usize, usize) {
+    let mut layer = 0;
+    let mut first = 0;
+    let mut capacity = 1;
+    while slot >= first + capacity {
+        first += capacity;
+        layer += 1;
+        capacity = STONES_PER_LAYER * layer * layer;
+    }
+    (layer, slot - first)
+}
+
+/* Where a slot is on the ball before it has rolled anywhere. Each layer is a sphere with its slots
+ * placed using the Fibonacci sphere algorithm, offset by half a step so there is no division by
+ * zero for layers holding a single slot.
+ * https://stackoverflow.com/questions/9600801/evenly-distributing-n-points-on-a-sphere
+ */
+fn slot_position(slot: usize) -> Vec3 {
+    let (layer, index) = slot_layer(slot);
+    if layer == 0 {
+        return Vec3::ZERO;
+    }
+    let capacity = (STONES_PER_LAYER * layer * layer) as f32;
+    let y = 1. - (index as f32 + 0.5) / capacity * 2.;
+    let radius = (1. - y * y).sqrt();
+    let theta = GOLDEN_ANGLE * index as f32;
+    layer as f32 * LAYER_SPACING * Vec3::new(theta.cos() * radius, y, theta.sin() * radius)
+}
+
+impl Player {
+    pub fn new(num_stones: usize) -> Self {
+        let mut player = Self {
+            stones: vec![],
+            orientation: Quat::IDENTITY,
+            position: Vec2::ZERO,
+            angular_velocity: Vec3::ZERO,
+        };
+        for _ in 0..num_stones {
+            player.add_stone(Stone::default());
+        }
+        player
+    }
+
+    /* The distance from the center to the outer edge of the outermost stone. A ball without any
+     * stones is treated as being the size of a single stone.
+     */
+    pub fn radius(&self) -> f32 {
+        self.stones
+            .iter()
+            .map(|s| s.position.length() + s.size)
+            .fold(STONE_SIZE, f32::max)
+    }
+
+    pub fn mass(&self) -> f32 {
+        self.stones.iter().map(|s| s.material.weight).sum()
+    }
+
+    pub fn max_speed(&self) -> f32 {
+        self.radius() * SPEED_PER_RADIUS
+    }
+
+    pub fn acceleration(&self) -> f32 {
+        let mass = self.mass().max(Material::ROCK.weight);
+        self.max_speed() / ACCELERATION_TICKS * (REFERENCE_MASS / mass).cbrt()
+    }
+
+    /* The shape used for collisions with the world if the ball was at the given position. */
+    pub fn collision_shape(&self, position: Vec2) -> Polygon {
+        Polygon::new_regular(COLLISION_SIDES, self.radius(), position, 0.)
+    }
+
+    /* How much the camera should magnify the world for the ball to fit on screen. */
+    pub fn zoom(&self) -> f32 {
+        (REFERENCE_RADIUS / self.radius()).min(1.)
+    }
+
+    pub fn accelerate(&mut self, normalized_amount: &Vec2) {
+        let velocity = self.velocity() + normalized_amount * self.acceleration();
+        self.set_velocity(velocity.clamp_length_max(self.max_speed()));
+    }
+
+    /* With no input the ball keeps rolling, only slowed down by rolling resistance. */
+    pub fn relax(&mut self) {
+        self.set_velocity(self.velocity().move_towards(Vec2::ZERO, ROLLING_RESISTANCE));
+    }
+
+    pub fn next_position(&self) -> Vec2 {
+        self.position + self.velocity()
+    }
+
+    /* Rolling without slipping means the contact point with the ground is at rest, so
+     * `velocity = angular_velocity x (radius * z)`.
+     */
+    pub fn velocity(&self) -> Vec2 {
+        (self.angular_velocity.cross(Vec3::Z) * self.radius()).truncate()
+    }
+
+    pub fn set_velocity(&mut self, velocity: Vec2) {
+        self.angular_velocity = Vec3::Z.cross(velocity.extend(0.)) / self.radius();
+    }
+
+    pub fn angular_velocity(&self) -> Vec3 {
+        self.angular_velocity
+    }
+
+    /* Rotate the stones around the center of the player by the angle covered this tick, which
+     * makes the arc length rolled over equal to the distance travelled.
+     */
+    pub fn advance(&mut self) {
+        if self.angular_velocity != Vec3::ZERO {
+            self.position = self.next_position();
+            let rotation = Quat::from_scaled_axis(self.angular_velocity);
+            self.orientation = (rotation * self.orientation).normalize();
+            self.stones
+                .iter_mut()
+                .for_each(|s| s.position = rotation * s.position);
+        }
+    }
+
+    pub fn position(&self) -> Vec2 {
+        self.position
+    }
+
+    /* Moves the ball somewhere else and brings it to a stop. */
+    pub fn teleport(&mut self, position: Vec2) {
+        self.position = position;
+        self.angular_velocity = Vec3::ZERO;
+    }
+
+    pub fn stones(&self) -> std::slice::Iter<'_, Stone> {
+        self.stones.iter()
+    }
+
+    /* Knocks up to `count` stones off the ball and returns them. The outermost, least sticky
+     * stones come off first, stones with a stickiness of 1 never do, and the ball always keeps at
+     * least one stone.
+     */
+    pub fn shed(&mut self, count: usize) -> Vec<Stone> {
+        let loose = |s: &Stone| s.material.stickiness < 1.;
+        let looseness = |s: &Stone| s.position.length() * (1. - s.material.stickiness);
+        let count = count
+            .min(self.stones.len().saturating_sub(1))
+            .min(self.stones.iter().filter(|s| loose(s)).count());
+        self.stones.sort_by(|a, b| {
+            loose(a)
+                .cmp(&loose(b))
+                .then(looseness(a).total_cmp(&looseness(b)))
+        });
+        self.stones.split_off(self.stones.len() - count)
+    }
+
+    /* Finds the free slot in the innermost layer that is not full yet. If a direction is given,
+     * the slot in that layer that currently faces closest to it is picked.
+     */
+    fn free_slot(&self, direction: Option<Vec3>) -> usize {
+        let mut taken: Vec<usize> = self.stones.iter().map(|s| s.slot).collect();
+        taken.sort_unstable();
+        let first_free = (0..)
+            .find(|slot| taken.binary_search(slot).is_err())
+            .unwrap();
+        let Some(direction) = direction else {
+            return first_free;
+        };
+
+        let (layer, _) = slot_layer(first_free);
+        (first_free..)
+            .take_while(|slot| slot_layer(*slot).0 == layer)
+            .filter(|slot| taken.binary_search(slot).is_err())
+            .max_by(|a, b| {
+                let facing = |slot: usize| (self.orientation * slot_position(slot)).dot(direction);
+                facing(*a).total_cmp(&facing(*b))
+            })
+            .unwrap_or(first_free)
+    }
+
+    fn place(&mut self, mut stone: Stone, slot: usize) {
+        stone.slot = slot;
+        stone.position = self.orientation * slot_position(slot);
+        self.stones.push(stone);
+    }
+
+    /* Puts a stone into the next free slot of the ball. */
+    pub fn add_stone(&mut self, stone: Stone) {
+        let slot = self.free_slot(None);
+        self.place(stone, slot);
+    }
+
+    /* Sticks a stone onto the ball, in the free slot facing closest to where it was picked up. */
+    pub fn attach(&mut self, stone: Stone, world_position: Vec2) {
+        let direction = (world_position - self.position)
+            .extend(0.)
+            .normalize_or_zero();
+        let slot = self.free_slot(Some(direction));
+        self.place(stone, slot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_matches_distance() {
+        let mut player = Player::new(12);
+        let circumference = std::f32::consts::TAU * player.radius();
+        let start = player.stones.clone();
+
+        player.set_velocity(Vec2::new(circumference / 100., 0.));
+        for _ in 0..100 {
+            player.advance();
+        }
+
+        assert!((player.position().x - circumference).abs() < 1e-4);
+        for (before, after) in start.iter().zip(player.stones()) {
+            assert!(
+                before.position().distance(after.position()) < 1e-3,
+                "{} moved to {}",
+                before.position(),
+                after.position()
+            );
+        }
+    }
+
+    #[test]
+    fn test_keeps_rolling_without_input() {
+        let mut player = Player::new(12);
+        player.accelerate(&Vec2::X);
+        let speed = player.velocity().length();
+
+        player.relax();
+        player.advance();
+        assert!(player.velocity().length() > 0.);
+        assert!((speed - player.velocity().length() - ROLLING_RESISTANCE).abs() < 1e-6);
+        assert!(player.angular_velocity().y > 0.);
+    }
+
+    #[test]
+    fn test_heavier_ball_takes_longer_to_speed_up() {
+        let small = Player::new(12);
+        let large = Player::new(500);
+        assert!(large.mass() > small.mass());
+        assert!(
+            large.max_speed() / large.acceleration() > small.max_speed() / small.acceleration()
+        );
+        assert!(large.collision_shape(Vec2::ZERO).contains_point(Vec2::ZERO));
+    }
+
+    #[test]
+    fn test_shed_and_attach() {
+        let mut player = Player::new(12);
+        let count = player.stones().len();
+
+        let shed = player.shed(3);
+        assert_eq!(shed.len(), 3);
+        assert_eq!(player.stones().len(), count - 3);
+        assert!(shed.iter().all(|s| s.position().length() > 0.));
+
+        assert_eq!(player.shed(100).len(), count - 4);
+        assert_eq!(player.stones().len(), 1);
+
+        player.attach(Stone::default(), Vec2::new(0., 1.));
+        assert_eq!(player.stones().len(), 2);
+        assert!(player.stones().last().unwrap().position().y > 0.);
+
+        let glue = Material {
+            weight: 1.,
+            stickiness: 1.,
+        };
+        player.attach(
+            Stone::new(6, STONE_SIZE, Vec3::ONE, glue),
+            Vec2::new(1., 0.),
+        );
+        assert_eq!(player.shed(100).len(), 2);
+        assert_eq!(player.stones().len(), 1);
+        assert_eq!(player.stones().next().unwrap().material(), glue);
+    }
+
+    fn assert_packed(num_stones: usize) {
+        let player = Player::new(num_stones);
+        assert_eq!(player.stones().len(), num_stones);
+        assert!(player.radius().is_finite());
+        assert!(player.velocity().is_finite());
+
+        /* Stones are only sure not to overlap when their centers are at least as far apart as the
+         * sizes of both added together.
+         */
+        let stones: Vec<&Stone> = player.stones().collect();
+        assert!(stones.iter().all(|s| s.position().is_finite()));
+        for (i, a) in stones.iter().enumerate() {
+            for b in stones[i + 1..].iter() {
+                let spacing = a.size() + b.size();
+                let (a, b) = (a.position(), b.position());
+                assert!(a.distance(b) >= spacing, "{} and {} overlap", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_packing() {
+        assert_packed(0);
+        assert_packed(1);
+        assert_packed(2);
+        assert_packed(1000);
+
+        assert_eq!(
+            Player::new(1).stones().next().unwrap().position(),
+            Vec3::ZERO
+        );
+        assert!((Player::new(2).radius() - LAYER_SPACING - STONE_SIZE).abs() < 1e-6);
+
+        /* A ball of 1000 stones is a solid sphere, so its radius grows with the cube root of the
+         * number of stones rather than the square root.
+         */
+        let radius = Player::new(1000).radius();
+        assert!(
+            radius < 7. * LAYER_SPACING + STONE_SIZE,
+            "Radius {}",
+            radius
+        );
+        assert!(radius > 5. * LAYER_SPACING, "Radius {}", radius);
+    }
+
+    #[test]
+    fn test_fills_free_slots_first() {
+        let mut player = Player::new(20);
+        let shed = player.shed(5);
+        let mut freed: Vec<usize> = shed.iter().map(|s| s.slot).collect();
+        freed.sort_unstable();
+
+        for stone in shed {
+            player.add_stone(stone);
+        }
+        let mut slots: Vec<usize> = player.stones().map(|s| s.slot).collect();
+        slots.sort_unstable();
+        assert_eq!(slots, (0..20).collect::<Vec<usize>>());
+        assert!(freed.iter().all(|slot| slots.contains(slot)));
+    }
+}
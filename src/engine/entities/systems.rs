@@ -0,0 +1,287 @@
+use crate::engine::cells::Cells;
+use crate::engine::entities::{Entities, Entity, Pickup, Velocity};
+use crate::engine::game::Command;
+use crate::engine::primitives::Polygon;
+use crate::engine::world::World;
+use glam::Vec2;
+
+/* Enemies closer than this to each other steer apart, so they do not clump into a single blob. */
+const SEPARATION_DISTANCE: f32 = 0.1;
+/* Once close to the target, enemies go straight for it instead of following the flow field. */
+const DIRECT_CHASE_DISTANCE: f32 = 0.15;
+
+/* Moves everything that has both a transform and a velocity, keeping moving pickups up to date in
+ * the spatial index.
+ */
+pub fn movement(entities: &mut Entities) {
+    let mut moved_pickups = vec![];
+    for (entity, Velocity(velocity)) in entities.velocities.iter() {
+        if let Some(transform) = entities.transforms.get_mut(entity) {
+            transform.position += *velocity;
+            if *velocity != Vec2::ZERO && entities.pickups.contains_key(entity) {
+                moved_pickups.push(*entity);
+            }
+        }
+    }
+    moved_pickups
+        .into_iter()
+        .for_each(|entity| entities.index_pickup(entity));
+}
+
+/* Pushes enemies back out of the tiles they moved into, and stops them from moving any further
+ * into them, so they go around the terrain like the bodies in `World::step`.
+ */
+pub fn terrain(entities: &mut Entities, world: &World) {
+    let enemies: Vec<Entity> = entities.enemies.keys().copied().collect();
+    for entity in enemies {
+        let Some(shape) = entities.world_shape(entity) else {
+            continue;
+        };
+        for tile in world.find_in_area(&shape) {
+            /* The shape is looked up again since resolving an earlier tile may have moved it. */
+            let Some((normal, depth, _)) = entities
+                .world_shape(entity)
+                .and_then(|shape| shape.contact(tile))
+            else {
+                continue;
+            };
+            if let Some(transform) = entities.transforms.get_mut(&entity) {
+                transform.position -= normal * depth;
+            }
+            if let Some(Velocity(velocity)) = entities.velocities.get_mut(&entity) {
+                *velocity -= normal * velocity.dot(normal).max(0.);
+            }
+        }
+    }
+}
+
+/* Steers every enemy towards the target, following the flow field through the open cells of the
+ * world. Each enemy seeks the centroid of the next cell on its way (or the target itself when
+ * close enough or in the same cell), while being pushed away from enemies that are too close.
+ * Enemies in cells the target cannot be reached from do not seek anything. The resulting steering
+ * force is limited, which gives the enemies some inertia.
+ */
+pub fn steering(
+    entities: &mut Entities,
+    cells: &Cells,
+    flow_field: &[Option<usize>],
+    target: Vec2,
+) {
+    let positions: Vec<(Entity, Vec2)> = entities
+        .enemies
+        .keys()
+        .flat_map(|e| entities.transforms.get(e).map(|t| (*e, t.position)))
+        .collect();
+    let target_cell = cells.cell_at(target);
+
+    for (entity, position) in positions.iter() {
+        let Some(enemy) = entities.enemies.get_mut(entity) else {
+            continue;
+        };
+        let Some(Velocity(velocity)) = entities.velocities.get_mut(entity) else {
+            continue;
+        };
+
+        let waypoint = match cells.cell_at(*position) {
+            _ if position.distance(target) < DIRECT_CHASE_DISTANCE => Some(target),
+            Some(cell) if Some(cell) == target_cell => Some(target),
+            Some(cell) => flow_field
+                .get(cell)
+                .copied()
+                .flatten()
+                .and_then(|next| cells.get(next))
+                .map(|next| next.centroid()),
+            None => None,
+        };
+
+        let mut desired = waypoint.map_or(Vec2::ZERO, |waypoint| {
+            (waypoint - position).normalize_or_zero() * enemy.max_speed
+        });
+        if enemy.cooldown > 0 {
+            enemy.cooldown -= 1;
+            desired = -desired;
+        }
+
+        let separation: Vec2 = positions
+            .iter()
+            .filter(|(other, _)| other != entity)
+            .map(|(_, other)| position - other)
+            .filter(|away| away.length() < SEPARATION_DISTANCE)
+            .map(|away| away.normalize_or_zero() * (SEPARATION_DISTANCE - away.length()))
+            .sum();
+
+        let force = (desired - *velocity + separation).clamp_length_max(enemy.max_force);
+        *velocity = (*velocity + force).clamp_length_max(enemy.max_speed);
+    }
+}
+
+/* Finds the enemies touching the given area that are not backing off, bounces them away from its
+ * center and makes them back off for a while. Returns the enemies that hit.
+ */
+pub fn enemy_hits(entities: &mut Entities, area: &Polygon, cooldown: u32) -> Vec<Entity> {
+    let center = area.centroid();
+    let hits: Vec<Entity> = entities
+        .enemies
+        .iter()
+        .filter(|(_, enemy)| enemy.cooldown == 0)
+        .map(|(entity, _)| *entity)
+        .filter(|entity| {
+            entities
+                .world_shape(*entity)
+                .is_some_and(|shape| shape.collides_with(area))
+        })
+        .collect();
+
+    for entity in hits.iter() {
+        if let Some(enemy) = entities.enemies.get_mut(entity) {
+            enemy.cooldown = cooldown;
+            let position = entities
+                .transforms
+                .get(entity)
+                .map_or(center, |t| t.position);
+            let away = (position - center).normalize_or_zero() * enemy.max_speed;
+            entities.velocities.insert(*entity, Velocity(away));
+        }
+    }
+    hits
+}
+
+/* Marks every available pickup touching the given area as collected, and returns them. */
+pub fn pickups(entities: &mut Entities, area: &Polygon) -> Vec<Entity> {
+    let touched: Vec<Entity> = entities
+        .pickups_in_area(area)
+        .into_iter()
+        .filter(|entity| entities.pickups.get(entity) == Some(&Pickup::Available))
+        .collect();
+    touched.iter().for_each(|entity| {
+        entities.pickups.insert(*entity, Pickup::Collected);
+    });
+    touched
+}
+
+/* Draws every visible entity, relative to the camera. Collected pickups are not drawn. */
+pub fn render(entities: &Entities, camera: Vec2, zoom: f32, command_arena: &mut Vec<Command>) {
+    for (entity, style) in entities.render_styles.iter() {
+        if entities.pickups.get(entity) == Some(&Pickup::Collected) {
+            continue;
+        }
+        if let Some(shape) = entities.world_shape(*entity) {
+            command_arena.push(Command::RenderFilledPolygon((
+                shape.vertices().map(|v| (v - camera) * zoom).collect(),
+                style.colour,
+                None,
+            )));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::world::TileKind;
+
+    #[test]
+    fn test_systems() {
+        let mut entities = Entities::new();
+        let near = entities.spawn_collectible(Vec2::new(1., 0.));
+        let far = entities.spawn_collectible(Vec2::new(5., 0.));
+        entities
+            .velocities
+            .insert(near, Velocity(Vec2::new(-0.5, 0.)));
+
+        let area = Polygon::new_regular(4, 0.1, Vec2::ZERO, 0.);
+        assert!(pickups(&mut entities, &area).is_empty());
+
+        movement(&mut entities);
+        movement(&mut entities);
+        assert_eq!(pickups(&mut entities, &area), vec![near]);
+        assert!(pickups(&mut entities, &area).is_empty());
+
+        let mut commands = vec![];
+        render(&entities, Vec2::ZERO, 1., &mut commands);
+        assert_eq!(commands.len(), 1);
+
+        let far_area = Polygon::new_regular(4, 0.1, Vec2::new(5., 0.), 0.);
+        assert_eq!(entities.pickups_in_area(&far_area), vec![far]);
+        entities.despawn(far);
+        assert!(!entities.is_alive(far));
+        assert_eq!(entities.len(), 1);
+        assert!(!entities.transforms.contains_key(&far));
+        assert!(entities.pickups_in_area(&far_area).is_empty());
+    }
+
+    #[test]
+    fn test_collectibles() {
+        let mut entities = Entities::new();
+        let spots: Vec<Vec2> = (0..30).map(|i| Vec2::new(i as f32 * 0.1, 0.)).collect();
+        let collectibles: Vec<Entity> = spots
+            .iter()
+            .map(|spot| entities.spawn_collectible(*spot))
+            .collect();
+
+        let area = Polygon::new_regular(4, 0.01, spots[17], 0.);
+        assert_eq!(pickups(&mut entities, &area), vec![collectibles[17]]);
+    }
+
+    #[test]
+    fn test_enemies_chase_and_hit() {
+        let cells = Cells::new(
+            (0..12)
+                .map(|i| {
+                    let rotation = (i % 2) as f32 * std::f32::consts::PI;
+                    Polygon::new_triangle(0.2, Vec2::new(i as f32 * 0.1, 0.), rotation)
+                })
+                .collect(),
+        );
+        let target = cells.get(11).unwrap().centroid();
+        let flow_field = cells.flow_field(target);
+
+        let mut entities = Entities::new();
+        let enemy = entities.spawn_enemy(Vec2::ZERO);
+        let area = Polygon::new_regular(8, 0.03, target, 0.);
+
+        let mut hits = vec![];
+        for _ in 0..500 {
+            steering(&mut entities, &cells, &flow_field, target);
+            movement(&mut entities);
+            hits = enemy_hits(&mut entities, &area, 10);
+            if !hits.is_empty() {
+                break;
+            }
+        }
+        assert_eq!(hits, vec![enemy]);
+        assert!(enemy_hits(&mut entities, &area, 10).is_empty());
+
+        /* An enemy outside of the cells has no way to the target and stays where it is. */
+        let stranded = entities.spawn_enemy(Vec2::new(0.5, 1.));
+        for _ in 0..50 {
+            steering(&mut entities, &cells, &flow_field, target);
+            movement(&mut entities);
+        }
+        assert_eq!(entities.transforms[&stranded].position, Vec2::new(0.5, 1.));
+    }
+
+    #[test]
+    fn test_enemies_stay_out_of_tiles() {
+        let mut world = World::default();
+        let wall = Polygon::new_regular(4, 0.5, Vec2::new(0.5, 0.), std::f32::consts::FRAC_PI_4);
+        world.add_tile(wall.clone(), TileKind::Solid);
+
+        let mut entities = Entities::new();
+        let enemy = entities.spawn_enemy(Vec2::new(-0.5, 0.));
+        entities
+            .velocities
+            .insert(enemy, Velocity(Vec2::new(0.01, 0.)));
+        for _ in 0..100 {
+            movement(&mut entities);
+            terrain(&mut entities, &world);
+        }
+        let shape = entities.world_shape(enemy).unwrap();
+        assert!(
+            shape
+                .contact(&wall)
+                .is_none_or(|(_, depth, _)| depth < 1e-4)
+        );
+        assert!(entities.velocities[&enemy].0.x.abs() < 1e-6);
+    }
+}
@@ -1,42 +1,243 @@
-use crate::engine::entities::Player;
+use crate::engine::entities::{COLLECTIBLE_COLOUR, Entities, Player, systems};
+use crate::engine::level::{LevelError, LevelFile};
 use crate::engine::primitives::Polygon;
-use crate::engine::world::{GeneratorResult, World, WorldGenerator};
+use crate::engine::triggers::Event;
+use crate::engine::world::{GeneratorResult, LevelConfig, TileKind, World, WorldGenerator};
 use glam::{Vec2, Vec3};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::time::Duration;
 
+/* An outline drawn just inside the edge of a shape, as its colour and width. */
+pub type Stroke = (Vec3, f32);
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Command {
     Clear(Vec3),
-    RenderCircle((Vec2, f32, Vec3)),
-    RenderFilledPolygon((Vec<Vec2>, Vec3)),
+    RenderCircle((Vec2, f32, Vec3, Option<Stroke>)),
+    RenderFilledPolygon((Vec<Vec2>, Vec3, Option<Stroke>)),
+}
+
+/* Walls and stones get a darker outline of their own colour, so neighbouring shapes of the same
+ * colour can still be told apart. The width is in screen coordinates, so it stays the same as the
+ * camera zooms out.
+ */
+const OUTLINE_WIDTH: f32 = 0.004;
+const OUTLINE_SHADE: f32 = 0.6;
+
+pub fn outline(colour: Vec3) -> Option<Stroke> {
+    Some((colour * OUTLINE_SHADE, OUTLINE_WIDTH))
 }
 
+#[derive(Serialize, Deserialize)]
 enum GameState {
     Generating(WorldGenerator),
     Ready(World),
+    Won(World),
 }
 
+/* Reaching the exit of this level wins the game instead of moving on to the next one. */
+pub const FINAL_LEVEL: usize = 9;
+/* How quickly the camera zoom catches up with the size of the ball, as a fraction per tick. */
+const ZOOM_RATE: f32 = 0.05;
+/* Hitting a wall at more than this fraction of top speed knocks stones off the ball, one more for
+ * every further multiple of it.
+ */
+const SHED_IMPACT: f32 = 0.4;
+/* The fraction of the speed it hit a bouncy tile with that the ball bounces back off it with. */
+const BOUNCINESS: f32 = 0.8;
+/* The fraction of its speed the ball keeps every tick it touches a sticky tile. */
+const STICKY_DRAG: f32 = 0.8;
+const CONVEYOR_SPEED: f32 = 0.004;
+/* Hitting a breakable tile at more than this fraction of top speed knocks a chunk out of it, this
+ * many times the size of the ball.
+ */
+const BREAK_IMPACT: f32 = 0.6;
+const BREAK_SCALE: f32 = 1.5;
+/* Touching lava burns a stone off the ball every this many ticks. */
+const BURN_COOLDOWN: u32 = 20;
+/* The HUD shows one pip per collectible, in screen coordinates. */
+const HUD_ORIGIN: Vec2 = Vec2::new(-0.9, 0.5);
+const HUD_PIP_SPACING: f32 = 0.04;
+const HUD_PIP_RADIUS: f32 = 0.012;
+const EXIT_LOCKED_COLOUR: Vec3 = Vec3::new(0.2, 0.3, 0.2);
+pub const EXIT_UNLOCKED_COLOUR: Vec3 = Vec3::new(0.2, 1., 0.2);
+/* Every enemy hit knocks this many stones off the ball, after which the enemy backs off for a
+ * number of ticks before it can hit again.
+ */
+const ENEMY_HIT_STONES: usize = 2;
+const ENEMY_HIT_COOLDOWN: u32 = 60;
+/* Once the exit is unlocked, an arrow this far outside the ball points the way there. */
+const HINT_DISTANCE: f32 = 0.06;
+const HINT_SIZE: f32 = 0.04;
+
+#[derive(Serialize, Deserialize)]
 pub struct Game {
     state: GameState,
+    rng: Pcg64,
+    level: usize,
+    config: LevelConfig,
     player: Player,
+    entities: Entities,
+    zoom: f32,
+    score: usize,
+    collectibles: usize,
+    /* Collectibles picked up over every level so far, and how many of them win the game early. */
+    collected: usize,
+    collectibles_to_win: Option<usize>,
+    /* The flow field towards the player, along with the cell the player was in when it was
+     * computed. It only needs recomputing once the player moves to another cell.
+     */
+    flow_field: Option<(usize, Vec<Option<usize>>)>,
+    /* The way to the exit, cached the same way as the flow field. */
+    hint: Option<(usize, Vec<Vec2>)>,
+    burn_cooldown: u32,
+    events: VecDeque<Event>,
 }
 
 impl Game {
     pub fn new() -> Self {
+        Self::new_at_level(0)
+    }
+
+    pub fn new_at_level(level: usize) -> Self {
+        Self::new_with_seed(level, rand::random())
+    }
+
+    /* Every level of a game comes from the same random number generator, so two games started
+     * with the same seed generate the same levels.
+     */
+    pub fn new_with_seed(level: usize, seed: u64) -> Self {
+        Self::new_with_config(level, LevelConfig::for_level(level), seed)
+    }
+
+    /* Like `new_with_seed`, but generates the first level with the given config instead of the
+     * usual one for its level number. The levels after it go back to the usual configs.
+     */
+    pub fn new_with_config(level: usize, config: LevelConfig, seed: u64) -> Self {
+        let mut rng = Pcg64::seed_from_u64(seed);
         Self {
-            state: GameState::Generating(World::generator(0.2, Vec2::new(2., 2.))),
+            state: GameState::Generating(World::generator(config.clone(), rng.random())),
+            rng,
+            level,
+            config,
             player: Player::new(12),
+            entities: Entities::new(),
+            zoom: 1.,
+            score: 0,
+            collectibles: 0,
+            collected: 0,
+            collectibles_to_win: None,
+            flow_field: None,
+            hint: None,
+            burn_cooldown: 0,
+            events: VecDeque::new(),
         }
     }
 
+    /* By default the game is won by getting out of the final level. This also wins it once `count`
+     * collectibles have been picked up, over all levels together.
+     */
+    pub fn with_collectibles_to_win(mut self, count: usize) -> Self {
+        self.collectibles_to_win = Some(count);
+        self
+    }
+
+    /* Starts a game on a level loaded from a file instead of a generated one. The levels after it
+     * are generated as usual, carrying on from the given level number.
+     */
+    pub fn load(level: usize, file: &LevelFile) -> Result<Self, LevelError> {
+        let mut game = Self::new_at_level(level);
+        game.state = GameState::Ready(World::from_level(file)?);
+        game.start();
+        Ok(game)
+    }
+
+    /* The current level as a level file, once it is done generating. */
+    pub fn level_file(&self) -> Option<LevelFile> {
+        match self.state {
+            GameState::Generating(_) => None,
+            GameState::Ready(ref world) | GameState::Won(ref world) => Some(world.to_level()),
+        }
+    }
+
+    /* Populates a freshly generated or loaded world and puts the player at its spawn point. */
+    fn start(&mut self) {
+        let GameState::Ready(ref world) = self.state else {
+            return;
+        };
+        for spawn in world.collectible_spawns() {
+            self.entities.spawn_collectible(*spawn);
+        }
+        self.collectibles = world.collectible_spawns().len();
+        for spawn in world.enemy_spawns() {
+            self.entities.spawn_enemy(*spawn);
+        }
+        self.player.teleport(world.spawn());
+    }
+
+    /* Whether the level is done generating, so ticks go to playing it rather than waiting. */
+    pub fn is_ready(&self) -> bool {
+        matches!(self.state, GameState::Ready(_) | GameState::Won(_))
+    }
+
+    pub fn has_won(&self) -> bool {
+        matches!(self.state, GameState::Won(_))
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /* The number of collectibles picked up on this level. */
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    /* The number of collectibles needed to unlock the exit of this level. */
+    pub fn target(&self) -> usize {
+        let collectibles_to_unlock = match self.state {
+            GameState::Generating(_) => None,
+            GameState::Ready(ref world) | GameState::Won(ref world) => {
+                world.collectibles_to_unlock()
+            }
+        };
+        collectibles_to_unlock.map_or(self.collectibles, |count| count.min(self.collectibles))
+    }
+
+    pub fn is_exit_unlocked(&self) -> bool {
+        self.score >= self.target()
+    }
+
+    /* Throws away the current level and starts generating the next one. The player keeps the ball
+     * it has built up so far.
+     */
+    fn next_level(&mut self) {
+        self.level += 1;
+        self.config = LevelConfig::for_level(self.level);
+        self.state =
+            GameState::Generating(World::generator(self.config.clone(), self.rng.random()));
+        self.entities = Entities::new();
+        self.score = 0;
+        self.collectibles = 0;
+        self.flow_field = None;
+        self.hint = None;
+    }
+
     pub fn tick(
         mut self,
         movement: &Vec2,
         mut command_arena: Vec<Command>,
-    ) -> (Self, Vec<Command>) {
+        mut event_arena: Vec<Event>,
+    ) -> (Self, Vec<Command>, Vec<Event>) {
         use Command::*;
 
         command_arena.clear();
+        event_arena.clear();
         command_arena.push(Clear(Vec3::new(0., 0., 0.)));
+        let was_unlocked = self.is_exit_unlocked();
 
         match self.state {
             GameState::Generating(generator) => {
@@ -46,57 +247,384 @@ impl Game {
                             Vec2::ZERO,
                             0.1,
                             Vec3::new(1., 0., 1.),
+                            None,
                         )));
                         self.state = GameState::Generating(generator)
                     }
-                    GeneratorResult::Done(world) => self.state = GameState::Ready(world),
+                    GeneratorResult::Done(world) => {
+                        self.state = GameState::Ready(world);
+                        self.start();
+                    }
                 }
             }
-            GameState::Ready(ref world) => {
+            GameState::Ready(ref mut world) => {
                 if *movement == Vec2::ZERO {
                     self.player.relax();
                 } else {
                     self.player.accelerate(movement);
-                    let next_position = self.player.next_position();
-                    let area = Polygon::new(vec![
-                        next_position + Vec2::new(-0.01, 0.01),
-                        next_position + Vec2::new(0.01, 0.01),
-                        next_position + Vec2::new(0.01, -0.01),
-                        next_position + Vec2::new(-0.01, -0.01),
-                    ]);
-                    let mut min_displacement = Vec2::INFINITY;
-                    for possibly_collided in world.find_in_area(&area) {
-                        if let Some(displacement) = possibly_collided.collision_displacement(&area)
-                        {
-                            min_displacement = min_displacement.min(displacement);
+                }
+
+                /* Push the ball out of any tile it would end up in, which also makes it slide
+                 * along walls instead of stopping dead. What else happens depends on the kind of
+                 * tile. Only hitting the hard ones counts towards knocking stones off.
+                 */
+                let area = self.player.collision_shape(self.player.next_position());
+                let speed = self.player.velocity().length();
+                let mut velocity = self.player.velocity();
+                let mut impact = 0.;
+                let mut burning = false;
+                let mut chunks = vec![];
+                for (_, tile, kind) in world.find_tiles_in_area(&area) {
+                    let Some((normal, depth, _)) = tile.contact(&area) else {
+                        continue;
+                    };
+                    let push = normal * depth;
+                    match kind {
+                        TileKind::Solid => {
+                            velocity += push;
+                            impact += depth;
+                        }
+                        TileKind::Bouncy => velocity += push * (1. + BOUNCINESS),
+                        TileKind::Sticky => {
+                            velocity = (velocity + push) * STICKY_DRAG;
+                            impact += depth;
+                        }
+                        TileKind::Ice => {
+                            velocity = (velocity + push).normalize_or_zero() * speed;
+                        }
+                        TileKind::Lava => {
+                            velocity += push;
+                            impact += depth;
+                            burning = true;
+                        }
+                        TileKind::Conveyor => {
+                            velocity += push + normal.perp() * CONVEYOR_SPEED;
+                            impact += depth;
+                        }
+                        TileKind::Breakable => {
+                            velocity += push;
+                            impact += depth;
+                            if depth > self.player.max_speed() * BREAK_IMPACT {
+                                let mut chunk = area.clone();
+                                chunk.scale(BREAK_SCALE);
+                                chunks.extend(tile.intersection(&chunk));
+                            }
                         }
                     }
-                    if min_displacement.is_finite() {
-                        self.player.set_velocity(min_displacement);
+                }
+                /* Knocking a tile out altogether opens up new cells, so the way anywhere may have
+                 * changed.
+                 */
+                for chunk in chunks {
+                    if world.carve(&chunk) {
+                        self.flow_field = None;
+                        self.hint = None;
+                    }
+                }
+                self.player.set_velocity(velocity);
+
+                let threshold = self.player.max_speed() * SHED_IMPACT;
+                let mut shed = (impact / threshold) as usize;
+                self.burn_cooldown = self.burn_cooldown.saturating_sub(1);
+                if burning && self.burn_cooldown == 0 {
+                    shed += 1;
+                    self.burn_cooldown = BURN_COOLDOWN;
+                }
+                if shed > 0 {
+                    let center = self.player.position();
+                    let rebound = velocity.normalize_or_zero() * impact;
+                    for stone in self.player.shed(shed) {
+                        let outwards =
+                            stone.position().truncate().normalize_or_zero() * impact.max(threshold);
+                        world.add_loose_stone(stone, center, rebound + outwards);
                     }
                 }
 
                 self.player.advance();
 
-                for tile in world.iter() {
-                    command_arena.push(Command::RenderFilledPolygon((
-                        tile.vertices()
-                            .copied()
-                            .map(|v| v - self.player.position())
-                            .collect(),
-                        Vec3::ONE,
-                    )));
+                let position = self.player.position();
+                world.push_bodies(
+                    &self.player.collision_shape(position),
+                    self.player.velocity(),
+                );
+                world.step();
+                let player_area = self.player.collision_shape(position);
+                for (stone, position) in world.pick_up_stones(&player_area) {
+                    self.player.attach(stone, position);
                 }
 
-                for stone in self.player.stones() {
-                    command_arena.push(RenderFilledPolygon((
-                        stone.vertices().copied().collect(),
-                        Vec3::ONE,
-                    )));
+                if let Some(cell) = world.cells().cell_at(position)
+                    && self.flow_field.as_ref().is_none_or(|(c, _)| *c != cell)
+                {
+                    self.flow_field = Some((cell, world.cells().flow_field(position)));
                 }
+                if let Some((_, flow_field)) = &self.flow_field {
+                    systems::steering(&mut self.entities, world.cells(), flow_field, position);
+                }
+                systems::movement(&mut self.entities);
+                systems::terrain(&mut self.entities, world);
+                let picked_up = systems::pickups(&mut self.entities, &player_area).len();
+                self.score += picked_up;
+                self.collected += picked_up;
+
+                /* Carry out whatever has been queued up, then let the host know about it too. */
+                self.events.extend(world.update_triggers(&player_area));
+                while let Some(event) = self.events.pop_front() {
+                    match &event {
+                        Event::OpenDoor(tiles) => {
+                            for tile in tiles {
+                                world.break_tile(*tile);
+                            }
+                            self.flow_field = None;
+                            self.hint = None;
+                        }
+                        Event::SpawnEnemies(positions) => {
+                            for position in positions {
+                                self.entities.spawn_enemy(*position);
+                            }
+                        }
+                        Event::ShowMessage(_) => {}
+                    }
+                    event_arena.push(event);
+                }
+
+                let hits =
+                    systems::enemy_hits(&mut self.entities, &player_area, ENEMY_HIT_COOLDOWN);
+                for enemy in hits {
+                    let direction = self
+                        .entities
+                        .transforms
+                        .get(&enemy)
+                        .map_or(Vec2::ZERO, |t| (position - t.position).normalize_or_zero());
+                    let impact = self.player.max_speed();
+                    for stone in self.player.shed(ENEMY_HIT_STONES) {
+                        let outwards = stone.position().truncate().normalize_or_zero() * impact;
+                        world.add_loose_stone(stone, position, direction * impact + outwards);
+                    }
+                }
+
+                self.zoom += (self.player.zoom() - self.zoom) * ZOOM_RATE;
+            }
+            GameState::Won(_) => {
+                self.player.relax();
+                self.player.advance();
             }
         };
 
-        (self, command_arena)
+        if !was_unlocked && self.is_exit_unlocked() {
+            self.events
+                .push_back(Event::ShowMessage("The exit is open".to_string()));
+        }
+
+        if let GameState::Ready(ref world) = self.state {
+            /* The way to the exit only changes once the player gets into another cell. */
+            let position = self.player.position();
+            if self.is_exit_unlocked()
+                && let Some(cell) = world.cells().cell_at(position)
+                && self.hint.as_ref().is_none_or(|(c, _)| *c != cell)
+                && let Some(exit) = world.exit()
+            {
+                self.hint = world
+                    .path(position, exit.centroid())
+                    .map(|path| (cell, path));
+            }
+
+            let reached_exit = world
+                .exit()
+                .is_some_and(|exit| exit.collides_with(&self.player.collision_shape(position)));
+            let won = (reached_exit && self.is_exit_unlocked() && self.level >= FINAL_LEVEL)
+                || self
+                    .collectibles_to_win
+                    .is_some_and(|count| self.collected >= count);
+            if won {
+                self.state = match self.state {
+                    GameState::Ready(world) => GameState::Won(world),
+                    state => state,
+                };
+                event_arena.push(Event::ShowMessage("You made it out".to_string()));
+            } else if reached_exit && self.is_exit_unlocked() {
+                self.next_level();
+            }
+        }
+
+        if let GameState::Ready(ref world) | GameState::Won(ref world) = self.state {
+            self.render(world, &mut command_arena);
+        }
+
+        (self, command_arena, event_arena)
+    }
+}
+
+impl Game {
+    fn render(&self, world: &World, command_arena: &mut Vec<Command>) {
+        use Command::*;
+
+        let position = self.player.position();
+        let zoom = self.zoom;
+
+        for (tile, kind) in world.tiles() {
+            command_arena.push(RenderFilledPolygon((
+                tile.vertices().map(|v| (v - position) * zoom).collect(),
+                kind.colour(),
+                outline(kind.colour()),
+            )));
+        }
+
+        for (body, stone) in world.bodies() {
+            let colour = stone.map_or(Vec3::new(0.6, 0.5, 0.3), |s| s.colour());
+            command_arena.push(RenderFilledPolygon((
+                body.shape()
+                    .vertices()
+                    .map(|v| (v - position) * zoom)
+                    .collect(),
+                colour,
+                outline(colour),
+            )));
+        }
+
+        if let Some(exit) = world.exit() {
+            command_arena.push(RenderFilledPolygon((
+                exit.vertices().map(|v| (v - position) * zoom).collect(),
+                match self.is_exit_unlocked() {
+                    true => EXIT_UNLOCKED_COLOUR,
+                    false => EXIT_LOCKED_COLOUR,
+                },
+                None,
+            )));
+        }
+
+        systems::render(&self.entities, position, zoom, command_arena);
+
+        for stone in self.player.stones() {
+            command_arena.push(RenderFilledPolygon((
+                stone.polygon().vertices().map(|v| v * zoom).collect(),
+                stone.colour(),
+                outline(stone.colour()),
+            )));
+        }
+
+        self.render_hint(command_arena);
+        self.render_hud(command_arena);
+    }
+
+    /* An arrow next to the ball pointing at the next waypoint on the way to the exit. */
+    fn render_hint(&self, command_arena: &mut Vec<Command>) {
+        if !self.is_exit_unlocked() {
+            return;
+        }
+        let position = self.player.position();
+        let Some(waypoint) = self.hint.as_ref().and_then(|(_, path)| {
+            path.iter()
+                .find(|waypoint| waypoint.distance(position) > self.player.radius())
+        }) else {
+            return;
+        };
+
+        let direction = (*waypoint - position).normalize_or_zero();
+        let center = direction * (self.player.radius() * self.zoom + HINT_DISTANCE);
+        /* Triangles point up before they are rotated. */
+        let rotation = direction.to_angle() - std::f32::consts::FRAC_PI_2;
+        let arrow = Polygon::new_triangle(HINT_SIZE, center, rotation);
+        command_arena.push(Command::RenderFilledPolygon((
+            arrow.vertices().copied().collect(),
+            EXIT_UNLOCKED_COLOUR,
+            None,
+        )));
+    }
+
+    /* One pip per collectible needed to unlock the exit, lit up for the ones collected so far.
+     * They all turn green once the exit is unlocked.
+     */
+    fn render_hud(&self, command_arena: &mut Vec<Command>) {
+        let collected = match self.is_exit_unlocked() {
+            true => EXIT_UNLOCKED_COLOUR,
+            false => COLLECTIBLE_COLOUR,
+        };
+        for pip in 0..self.target() {
+            let colour = match pip < self.score {
+                true => collected,
+                false => Vec3::splat(0.3),
+            };
+            command_arena.push(Command::RenderCircle((
+                HUD_ORIGIN + Vec2::new(pip as f32 * HUD_PIP_SPACING, 0.),
+                HUD_PIP_RADIUS,
+                colour,
+                None,
+            )));
+        }
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::level::LEVEL_VERSION;
+
+    /* A level with nothing but an open square around the spawn and the given collectibles. */
+    fn open_level(collectibles: Vec<Vec2>, collectibles_to_unlock: Option<usize>) -> LevelFile {
+        LevelFile {
+            version: LEVEL_VERSION,
+            spawn: Vec2::ZERO,
+            tiles: vec![],
+            cells: vec![vec![
+                Vec2::new(-0.5, -0.5),
+                Vec2::new(0.5, -0.5),
+                Vec2::new(0.5, 0.5),
+                Vec2::new(-0.5, 0.5),
+            ]],
+            exit: None,
+            debris: vec![],
+            collectibles,
+            enemies: vec![],
+            triggers: vec![],
+            collectibles_to_unlock,
+        }
+    }
+
+    #[test]
+    fn test_pickups_unlock_the_exit() {
+        let collectibles = vec![Vec2::ZERO, Vec2::new(0.01, 0.), Vec2::new(0.4, 0.4)];
+        let mut game = Game::load(0, &open_level(collectibles, Some(2))).unwrap();
+        assert_eq!(game.score(), 0);
+        assert_eq!(game.target(), 2);
+        assert!(!game.is_exit_unlocked());
+
+        (game, _, _) = game.tick(&Vec2::ZERO, vec![], vec![]);
+        assert_eq!(game.score(), 2);
+        assert!(game.is_exit_unlocked());
+    }
+
+    #[test]
+    fn test_win() {
+        let level = LevelFile {
+            exit: Some(0),
+            ..open_level(vec![], None)
+        };
+        let (game, _, _) = Game::load(0, &level)
+            .unwrap()
+            .tick(&Vec2::ZERO, vec![], vec![]);
+        assert_eq!(game.level(), 1);
+        assert!(!game.has_won());
+
+        let (game, _, events) =
+            Game::load(FINAL_LEVEL, &level)
+                .unwrap()
+                .tick(&Vec2::ZERO, vec![], vec![]);
+        assert!(game.has_won());
+        assert!(matches!(events.as_slice(), [Event::ShowMessage(_)]));
+
+        let level = open_level(vec![Vec2::ZERO], None);
+        let (game, commands, _) = Game::load(0, &level)
+            .unwrap()
+            .with_collectibles_to_win(1)
+            .tick(&Vec2::ZERO, vec![], vec![]);
+        assert!(game.has_won());
+        assert!(commands.len() > 1);
     }
 }
@@ -0,0 +1,182 @@
+use crate::engine::primitives::PolygonError;
+use crate::engine::triggers::Trigger;
+use crate::engine::world::TileKind;
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::Path;
+
+/* Bumped whenever the layout of `LevelFile` changes, so old files are refused instead of being
+ * misread.
+ */
+pub const LEVEL_VERSION: u32 = 1;
+/* Binary level files start with this, so they can be told apart from text ones. */
+const MAGIC: &[u8; 4] = b"RRLV";
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(std::io::Error),
+    Text(ron::error::SpannedError),
+    TextWrite(ron::Error),
+    Decode(bincode::error::DecodeError),
+    Encode(bincode::error::EncodeError),
+    NotALevel,
+    UnsupportedVersion(u32),
+    InvalidPolygon(PolygonError),
+    NonConvexTile(usize),
+    NonConvexDebris(usize),
+    NoSuchCell(usize),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io(e) => write!(f, "Could not access the level file: {}", e),
+            LevelError::Text(e) => write!(f, "Could not read the level: {}", e),
+            LevelError::TextWrite(e) => write!(f, "Could not write the level: {}", e),
+            LevelError::Decode(e) => write!(f, "Could not read the level: {}", e),
+            LevelError::Encode(e) => write!(f, "Could not write the level: {}", e),
+            LevelError::NotALevel => write!(f, "This is not a level file"),
+            LevelError::UnsupportedVersion(version) => write!(
+                f,
+                "Level file version {} is not supported, expected {}",
+                version, LEVEL_VERSION
+            ),
+            LevelError::InvalidPolygon(e) => write!(f, "Invalid polygon in the level: {}", e),
+            LevelError::NonConvexTile(index) => write!(f, "Tile {} is not convex", index),
+            LevelError::NonConvexDebris(index) => write!(f, "Debris {} is not convex", index),
+            LevelError::NoSuchCell(index) => write!(f, "The exit cell {} does not exist", index),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl From<PolygonError> for LevelError {
+    fn from(e: PolygonError) -> Self {
+        LevelError::InvalidPolygon(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TileData {
+    pub vertices: Vec<Vec2>,
+    pub kind: TileKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TriggerData {
+    pub regions: Vec<Vec<Vec2>>,
+    pub trigger: Trigger,
+}
+
+/* Everything needed to play a level, as plain data that can be written by hand or saved from a
+ * generated world. Tiles have to be convex, and the tiles of doors are referred to by handle: the
+ * n-th tile in the file gets the handle with index n and generation 0 when it is loaded.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LevelFile {
+    pub version: u32,
+    pub spawn: Vec2,
+    pub tiles: Vec<TileData>,
+    /* The open cells making up the navigation mesh, and which one of them is the exit. */
+    pub cells: Vec<Vec<Vec2>>,
+    pub exit: Option<usize>,
+    pub debris: Vec<Vec<Vec2>>,
+    pub collectibles: Vec<Vec2>,
+    pub enemies: Vec<Vec2>,
+    pub triggers: Vec<TriggerData>,
+    /* How many collectibles need to be picked up to unlock the exit. `None` means all of them. */
+    pub collectibles_to_unlock: Option<usize>,
+}
+
+impl LevelFile {
+    pub fn from_ron(text: &str) -> Result<Self, LevelError> {
+        let level: Self = ron::from_str(text).map_err(LevelError::Text)?;
+        level.check_version()
+    }
+
+    pub fn to_ron(&self) -> Result<String, LevelError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(LevelError::TextWrite)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LevelError> {
+        let body = bytes.strip_prefix(MAGIC).ok_or(LevelError::NotALevel)?;
+        let (level, _): (Self, usize) =
+            bincode::serde::decode_from_slice(body, bincode::config::standard())
+                .map_err(LevelError::Decode)?;
+        level.check_version()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, LevelError> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(
+            bincode::serde::encode_to_vec(self, bincode::config::standard())
+                .map_err(LevelError::Encode)?,
+        );
+        Ok(bytes)
+    }
+
+    /* Files ending in `.ron` are read as text, anything else as binary. */
+    pub fn load(path: &Path) -> Result<Self, LevelError> {
+        if is_text(path) {
+            Self::from_ron(&std::fs::read_to_string(path).map_err(LevelError::Io)?)
+        } else {
+            Self::from_bytes(&std::fs::read(path).map_err(LevelError::Io)?)
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), LevelError> {
+        let contents = match is_text(path) {
+            true => self.to_ron()?.into_bytes(),
+            false => self.to_bytes()?,
+        };
+        std::fs::write(path, contents).map_err(LevelError::Io)
+    }
+
+    fn check_version(self) -> Result<Self, LevelError> {
+        match self.version {
+            LEVEL_VERSION => Ok(self),
+            version => Err(LevelError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+fn is_text(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "ron")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::world::World;
+
+    #[test]
+    fn test_hand_made_level() {
+        let level = LevelFile::from_ron(include_str!("../../levels/tutorial.ron")).unwrap();
+        let world = World::from_level(&level).unwrap();
+        assert_eq!(world.tiles().count(), 5);
+        assert!(world.exit().is_some());
+        assert_eq!(
+            world.path(level.spawn, Vec2::new(1.2, 0.)).map(|p| p.len()),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_refuses_other_files() {
+        let mut level = LevelFile::from_ron(include_str!("../../levels/tutorial.ron")).unwrap();
+        assert!(matches!(
+            LevelFile::from_bytes(b"not a level"),
+            Err(LevelError::NotALevel)
+        ));
+
+        level.version = LEVEL_VERSION + 1;
+        let bytes = level.to_bytes().unwrap();
+        assert!(matches!(
+            LevelFile::from_bytes(&bytes),
+            Err(LevelError::UnsupportedVersion(_))
+        ));
+    }
+}
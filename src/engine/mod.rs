@@ -1,5 +1,16 @@
-mod entities;
+pub mod arena;
+pub mod batch;
+pub mod cells;
+pub mod editor;
+pub mod entities;
 pub mod game;
-mod primitives;
-mod quadtree;
-mod world;
+pub mod level;
+pub mod physics;
+pub mod primitives;
+pub mod quadtree;
+pub mod replay;
+pub mod save;
+pub mod stats;
+pub mod svg;
+pub mod triggers;
+pub mod world;
@@ -0,0 +1,248 @@
+use crate::engine::primitives::Polygon;
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+/* Like the player, bodies move in world units per tick, so there is no time step to pass around. */
+const LINEAR_DAMPING: f32 = 0.98;
+const ANGULAR_DAMPING: f32 = 0.95;
+const RESTITUTION: f32 = 0.3;
+const DENSITY: f32 = 1000.;
+
+/* A loose polygon that can be pushed around. The shape is kept in world coordinates and moved in
+ * place every tick, with the position being its centroid.
+ */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RigidBody {
+    shape: Polygon,
+    position: Vec2,
+    velocity: Vec2,
+    angular_velocity: f32,
+    inverse_mass: f32,
+    inverse_inertia: f32,
+}
+
+impl RigidBody {
+    pub fn new(shape: Polygon) -> Self {
+        let mass = shape.area() * DENSITY;
+        let inertia = shape.second_moment_of_area() * DENSITY;
+        Self {
+            position: shape.centroid(),
+            shape,
+            velocity: Vec2::ZERO,
+            angular_velocity: 0.,
+            inverse_mass: 1. / mass,
+            inverse_inertia: 1. / inertia,
+        }
+    }
+
+    pub fn shape(&self) -> &Polygon {
+        &self.shape
+    }
+
+    pub fn position(&self) -> Vec2 {
+        self.position
+    }
+
+    pub fn velocity(&self) -> Vec2 {
+        self.velocity
+    }
+
+    pub fn angular_velocity(&self) -> f32 {
+        self.angular_velocity
+    }
+
+    pub fn mass(&self) -> f32 {
+        1. / self.inverse_mass
+    }
+
+    pub fn inertia(&self) -> f32 {
+        1. / self.inverse_inertia
+    }
+
+    pub fn is_resting(&self) -> bool {
+        self.velocity == Vec2::ZERO && self.angular_velocity == 0.
+    }
+
+    /* The velocity of the point of the body at the given world position. */
+    pub fn velocity_at(&self, point: Vec2) -> Vec2 {
+        self.velocity + self.angular_velocity * (point - self.position).perp()
+    }
+
+    pub fn apply_impulse(&mut self, impulse: Vec2, point: Vec2) {
+        self.velocity += impulse * self.inverse_mass;
+        self.angular_velocity += (point - self.position).perp_dot(impulse) * self.inverse_inertia;
+    }
+
+    pub fn translate(&mut self, offset: Vec2) {
+        self.position += offset;
+        self.shape.translate(offset);
+    }
+
+    pub fn integrate(&mut self) {
+        if self.is_resting() {
+            return;
+        }
+        self.translate(self.velocity);
+        self.shape
+            .rotate_around(self.angular_velocity, self.position);
+
+        self.velocity *= LINEAR_DAMPING;
+        self.angular_velocity *= ANGULAR_DAMPING;
+        if self.velocity.length() < 1e-5 && self.angular_velocity.abs() < 1e-4 {
+            self.velocity = Vec2::ZERO;
+            self.angular_velocity = 0.;
+        }
+    }
+}
+
+/* The inverse mass and inverse inertia of something involved in a collision, along with how fast
+ * it moves. Static tiles and the player are treated as having infinite mass, so they have zero
+ * inverse mass and are never pushed back.
+ */
+struct Participant {
+    inverse_mass: f32,
+    inverse_inertia: f32,
+    position: Vec2,
+    velocity: Vec2,
+    angular_velocity: f32,
+}
+
+impl Participant {
+    fn of(body: &RigidBody) -> Self {
+        Self {
+            inverse_mass: body.inverse_mass,
+            inverse_inertia: body.inverse_inertia,
+            position: body.position,
+            velocity: body.velocity,
+            angular_velocity: body.angular_velocity,
+        }
+    }
+
+    fn immovable(velocity: Vec2) -> Self {
+        Self {
+            inverse_mass: 0.,
+            inverse_inertia: 0.,
+            position: Vec2::ZERO,
+            velocity,
+            angular_velocity: 0.,
+        }
+    }
+
+    fn velocity_at(&self, point: Vec2) -> Vec2 {
+        self.velocity + self.angular_velocity * (point - self.position).perp()
+    }
+}
+
+/* Computes the impulse along the contact normal (pointing from `a` to `b`) that stops the two from
+ * moving into each other, as well as how far each one needs to be moved to stop overlapping. The
+ * impulse should be applied negated to `a` and as is to `b`.
+ */
+fn resolve(
+    a: &Participant,
+    b: &Participant,
+    (normal, depth, point): (Vec2, f32, Vec2),
+) -> Option<(Vec2, Vec2, Vec2)> {
+    let total_inverse_mass = a.inverse_mass + b.inverse_mass;
+    if total_inverse_mass == 0. {
+        return None;
+    }
+
+    let correction = normal * depth / total_inverse_mass;
+    let a_correction = -correction * a.inverse_mass;
+    let b_correction = correction * b.inverse_mass;
+
+    let relative_velocity = b.velocity_at(point) - a.velocity_at(point);
+    let approaching_speed = relative_velocity.dot(normal);
+    if approaching_speed > 0. {
+        return Some((Vec2::ZERO, a_correction, b_correction));
+    }
+
+    let a_arm = (point - a.position).perp_dot(normal);
+    let b_arm = (point - b.position).perp_dot(normal);
+    let denominator =
+        total_inverse_mass + a_arm * a_arm * a.inverse_inertia + b_arm * b_arm * b.inverse_inertia;
+    let magnitude = -(1. + RESTITUTION) * approaching_speed / denominator;
+
+    Some((normal * magnitude, a_correction, b_correction))
+}
+
+/* Bounces a body off something that does not move, such as a wall tile. */
+pub fn collide_with_static(body: &mut RigidBody, obstacle: &Polygon) {
+    collide_with_kinematic(body, obstacle, Vec2::ZERO);
+}
+
+/* Pushes a body out of the way of something that moves with the given velocity but is not pushed
+ * back, such as the player.
+ */
+pub fn collide_with_kinematic(body: &mut RigidBody, obstacle: &Polygon, velocity: Vec2) {
+    if let Some(contact) = obstacle.contact(&body.shape)
+        && let Some((impulse, _, correction)) = resolve(
+            &Participant::immovable(velocity),
+            &Participant::of(body),
+            contact,
+        )
+    {
+        body.apply_impulse(impulse, contact.2);
+        body.translate(correction);
+    }
+}
+
+pub fn collide_bodies(a: &mut RigidBody, b: &mut RigidBody) {
+    if let Some(contact) = a.shape.contact(&b.shape)
+        && let Some((impulse, a_correction, b_correction)) =
+            resolve(&Participant::of(a), &Participant::of(b), contact)
+    {
+        a.apply_impulse(-impulse, contact.2);
+        b.apply_impulse(impulse, contact.2);
+        a.translate(a_correction);
+        b.translate(b_correction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_impulse_spins_off_center() {
+        let mut body = RigidBody::new(Polygon::new_regular(4, 0.1, Vec2::ZERO, 0.));
+        body.apply_impulse(Vec2::new(0., 1.), body.position());
+        assert_eq!(body.angular_velocity(), 0.);
+        body.apply_impulse(Vec2::new(0., 1.), body.position() + Vec2::new(0.1, 0.));
+        assert!(body.angular_velocity() > 0.);
+        assert!(body.velocity().y > 0.);
+    }
+
+    #[test]
+    fn test_momentum_is_conserved() {
+        let mut a = RigidBody::new(Polygon::new_regular(4, 0.1, Vec2::ZERO, 0.));
+        let mut b = RigidBody::new(Polygon::new_regular(4, 0.1, Vec2::new(0.15, 0.), 0.));
+        a.apply_impulse(Vec2::new(a.mass() * 0.01, 0.), a.position());
+
+        let before = a.velocity() * a.mass() + b.velocity() * b.mass();
+        collide_bodies(&mut a, &mut b);
+        let after = a.velocity() * a.mass() + b.velocity() * b.mass();
+
+        assert!(b.velocity().x > 0.);
+        assert!(a.velocity().x < 0.01);
+        assert!(before.distance(after) < 1e-3 * before.length());
+        assert!(
+            a.shape()
+                .contact(b.shape())
+                .is_none_or(|(_, depth, _)| depth < 1e-5)
+        );
+    }
+
+    #[test]
+    fn test_stops_at_wall() {
+        let wall = Polygon::new_regular(4, 0.1, Vec2::new(0.2, 0.), 0.);
+        let mut body = RigidBody::new(Polygon::new_regular(4, 0.05, Vec2::ZERO, 0.));
+        body.apply_impulse(Vec2::new(body.mass() * 0.02, 0.), body.position());
+        for _ in 0..20 {
+            body.integrate();
+            collide_with_static(&mut body, &wall);
+        }
+        assert!(body.velocity().x <= 0.);
+        assert!(!wall.contains_point(body.position()));
+    }
+}
@@ -1,40 +1,100 @@
-use glam::{Mat2, Vec2};
+use glam::{Affine2, Vec2};
+use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::hash::{Hash, Hasher};
 use std::iter::zip;
-use std::sync::atomic::{AtomicUsize, Ordering};
-
-static POLYGON_ID: AtomicUsize = AtomicUsize::new(1);
 
 const SQRT_3_OVER_4: f32 = 1.732_050_8 / 4.;
 
-#[derive(Clone, Debug)]
+/* Vertices closer than this are considered to be the same vertex, and three vertices whose
+ * turning angle has a sine smaller than this are considered to be collinear.
+ */
+const EPSILON: f32 = 1e-5;
+
+#[derive(Debug, PartialEq)]
+pub enum PolygonError {
+    TooFewVertices(usize),
+    SelfIntersecting,
+    NotFinite,
+}
+
+impl fmt::Display for PolygonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolygonError::TooFewVertices(count) => write!(
+                f,
+                "A polygon needs at least 3 distinct, non-collinear vertices, got {}",
+                count
+            ),
+            PolygonError::SelfIntersecting => write!(f, "The polygon edges intersect each other"),
+            PolygonError::NotFinite => write!(f, "The polygon has a vertex at infinity or NaN"),
+        }
+    }
+}
+
+impl std::error::Error for PolygonError {}
+
+/* Polygons have no identity of their own. Whatever stores them hands out handles, see `Arena`, and
+ * equality compares the shapes. Only the vertices are stored, and loading them goes through
+ * `try_new`, so a loaded polygon is as valid as a freshly made one.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<Vec2>", into = "Vec<Vec2>")]
 pub struct Polygon {
-    id: usize,
     vertices: Vec<Vec2>,
     edges: Vec<(Vec2, Vec2)>,
 }
 
+impl TryFrom<Vec<Vec2>> for Polygon {
+    type Error = PolygonError;
+
+    fn try_from(vertices: Vec<Vec2>) -> Result<Self, Self::Error> {
+        Self::try_new(vertices)
+    }
+}
+
+impl From<Polygon> for Vec<Vec2> {
+    fn from(polygon: Polygon) -> Self {
+        polygon.vertices
+    }
+}
+
 impl fmt::Display for Polygon {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "({}: {:?})", self.id, self.vertices)
+        write!(f, "({:?})", self.vertices)
     }
 }
 
 impl Polygon {
     pub fn new(vertices: Vec<Vec2>) -> Self {
-        let id = POLYGON_ID.fetch_add(1, Ordering::Relaxed);
-        let edges = zip(
-            vertices.clone(),
-            vertices.clone().into_iter().cycle().skip(1),
-        )
-        .collect();
+        match Self::try_new(vertices) {
+            Ok(polygon) => polygon,
+            Err(e) => panic!("Invalid polygon: {}", e),
+        }
+    }
 
-        Self {
-            id,
-            vertices,
-            edges,
+    /* Validates the given vertices before building the polygon. Duplicate and collinear vertices
+     * are dropped, and the winding is normalized to be counter-clockwise, so every polygon that
+     * comes out of here is simple and has a consistent winding. It may still be concave, which
+     * can be checked with `is_convex` and fixed with `decompose`.
+     */
+    pub fn try_new(vertices: Vec<Vec2>) -> Result<Self, PolygonError> {
+        if !vertices.iter().all(|v| v.is_finite()) {
+            return Err(PolygonError::NotFinite);
         }
+        let mut vertices = remove_degenerate_vertices(vertices);
+        if vertices.len() < 3 {
+            return Err(PolygonError::TooFewVertices(vertices.len()));
+        }
+        if signed_area(&vertices) < 0. {
+            vertices.reverse();
+        }
+        if is_self_intersecting(&vertices) {
+            return Err(PolygonError::SelfIntersecting);
+        }
+
+        let edges = edges_of(&vertices);
+
+        Ok(Self { vertices, edges })
     }
 
     pub fn new_triangle(size: f32, center: Vec2, rotation: f32) -> Self {
@@ -43,38 +103,32 @@ impl Polygon {
         let top = center.y + size * SQRT_3_OVER_4;
         let bottom = center.y - size * SQRT_3_OVER_4;
 
-        let model = [
+        let mut triangle = Self::new(vec![
             Vec2::new(center.x, top),
             Vec2::new(left, bottom),
             Vec2::new(right, bottom),
-        ];
-
-        let vertices = model
-            .iter()
-            .map(|v| Mat2::from_angle(rotation) * (v - center) + center)
-            .collect();
-
-        Self::new(vertices)
+        ]);
+        triangle.rotate_around(rotation, center);
+        triangle
     }
 
+    /* The first vertex is `size` to the right of the center before the whole polygon is turned by
+     * `rotation` around it.
+     */
     pub fn new_regular(sides: usize, size: f32, center: Vec2, rotation: f32) -> Self {
         assert!(sides > 2);
 
         let angle = std::f32::consts::PI * 2. / sides as f32;
-        let model = (0..sides)
+        let vertices = (0..sides)
             .map(|s| {
-                let rot = s as f32 * (angle + rotation);
-                Vec2::new(rot.cos(), rot.sin()) * size
+                let rot = s as f32 * angle;
+                Vec2::new(rot.cos(), rot.sin()) * size + center
             })
-            .collect::<Vec<Vec2>>();
-
-        let vertices = model.iter().map(|v| v + center).collect();
-
-        Self::new(vertices)
-    }
+            .collect();
 
-    pub fn id(&self) -> usize {
-        self.id
+        let mut regular = Self::new(vertices);
+        regular.rotate_around(rotation, center);
+        regular
     }
 
     pub fn vertices(&self) -> std::slice::Iter<'_, Vec2> {
@@ -85,6 +139,131 @@ impl Polygon {
         self.edges.iter()
     }
 
+    pub fn area(&self) -> f32 {
+        signed_area(&self.vertices)
+    }
+
+    /* The centroid of the area, not the average of the vertices. These only agree for regular
+     * polygons.
+     */
+    pub fn centroid(&self) -> Vec2 {
+        let weighted = zip(self.vertices.iter(), self.vertices.iter().cycle().skip(1))
+            .map(|(a, b)| (a + b) * a.perp_dot(*b))
+            .sum::<Vec2>();
+        weighted / (6. * self.area())
+    }
+
+    /* Returns the minimum and maximum corners of the axis aligned bounding box. */
+    pub fn aabb(&self) -> (Vec2, Vec2) {
+        self.vertices.iter().fold(
+            (Vec2::INFINITY, Vec2::NEG_INFINITY),
+            |(min, max), vertex| (min.min(*vertex), max.max(*vertex)),
+        )
+    }
+
+    /* Transforms the polygon in place, so whatever handle refers to it stays valid. A mirroring
+     * transform flips the winding, so that gets normalized again.
+     */
+    pub fn transform(&mut self, transform: &Affine2) {
+        if let Err(e) = self.try_transform(transform) {
+            panic!("Invalid transform: {}", e);
+        }
+    }
+
+    /* Like `transform`, but the result is validated the way `try_new` does it. A transform that
+     * collapses the polygon, like scaling it by zero, is refused and leaves it as it was.
+     */
+    pub fn try_transform(&mut self, transform: &Affine2) -> Result<(), PolygonError> {
+        let vertices = self
+            .vertices
+            .iter()
+            .map(|v| transform.transform_point2(*v))
+            .collect();
+        *self = Self::try_new(vertices)?;
+        Ok(())
+    }
+
+    /* Moving and turning cannot change the shape, so these skip the checks. */
+    fn transform_rigid(&mut self, transform: &Affine2) {
+        self.vertices
+            .iter_mut()
+            .for_each(|v| *v = transform.transform_point2(*v));
+        self.edges = edges_of(&self.vertices);
+    }
+
+    pub fn translate(&mut self, offset: Vec2) {
+        self.transform_rigid(&Affine2::from_translation(offset));
+    }
+
+    pub fn rotate(&mut self, angle: f32) {
+        self.rotate_around(angle, self.centroid());
+    }
+
+    pub fn rotate_around(&mut self, angle: f32, pivot: Vec2) {
+        self.transform_rigid(
+            &(Affine2::from_translation(pivot)
+                * Affine2::from_angle(angle)
+                * Affine2::from_translation(-pivot)),
+        );
+    }
+
+    /* Scales the polygon about its centroid. Panics when the factor is zero, as that would collapse
+     * the polygon into a point.
+     */
+    pub fn scale(&mut self, factor: f32) {
+        let pivot = self.centroid();
+        self.transform(
+            &(Affine2::from_translation(pivot)
+                * Affine2::from_scale(Vec2::splat(factor))
+                * Affine2::from_translation(-pivot)),
+        );
+    }
+
+    /* Since the winding is always counter-clockwise, the polygon is convex when every corner
+     * turns left.
+     */
+    pub fn is_convex(&self) -> bool {
+        let count = self.vertices.len();
+        (0..count).all(|i| {
+            let a = self.vertices[i];
+            let b = self.vertices[(i + 1) % count];
+            let c = self.vertices[(i + 2) % count];
+            (b - a).perp_dot(c - b) > 0.
+        })
+    }
+
+    /* Splits the polygon into convex parts. Convex polygons are returned as is. Concave ones are
+     * triangulated by ear clipping, and then the Hertel-Mehlhorn algorithm merges neighbouring
+     * parts back together for as long as the merged part stays convex. This gives at most four
+     * times the optimal number of parts, which is plenty good for our purposes.
+     */
+    pub fn decompose(&self) -> Vec<Polygon> {
+        if self.is_convex() {
+            return vec![self.clone()];
+        }
+
+        let mut parts = triangulate(&self.vertices);
+        let mut merged = true;
+        while merged {
+            merged = false;
+            'search: for i in 0..parts.len() {
+                for j in (i + 1)..parts.len() {
+                    if let Some(part) = merge_convex(&parts[i], &parts[j], &self.vertices) {
+                        parts[i] = part;
+                        parts.swap_remove(j);
+                        merged = true;
+                        break 'search;
+                    }
+                }
+            }
+        }
+
+        parts
+            .into_iter()
+            .map(|part| Polygon::new(part.iter().map(|i| self.vertices[*i]).collect()))
+            .collect()
+    }
+
     /* The polygon (assumed to be convex) contains the given point if the cross product of each
      * edge and the vector from the beginning of such edge and the point all are in the same
      * direction (z axis of each cross product has the same sign).
@@ -147,21 +326,443 @@ impl Polygon {
         Some(displacement * min_axis.unwrap())
     }
 
+    /* Like `collision_displacement`, but with a unit normal that always points from this polygon
+     * towards the other one, the penetration depth along it and an estimate of the contact point.
+     * The contact point is the average of the vertices of either polygon that are inside the
+     * other, which is good enough for resolving collisions.
+     */
+    pub fn contact(&self, other: &Self) -> Option<(Vec2, f32, Vec2)> {
+        let mut depth = f32::INFINITY;
+        let mut normal = Vec2::ZERO;
+
+        for (start, end) in self.edges.iter().chain(other.edges.iter()) {
+            let axis = (end - start).perp().normalize_or_zero();
+            if axis == Vec2::ZERO {
+                continue;
+            }
+            let (min_lhs, max_lhs) = project(&self.vertices, axis);
+            let (min_rhs, max_rhs) = project(&other.vertices, axis);
+            let overlap = max_lhs.min(max_rhs) - min_lhs.max(min_rhs);
+            if overlap < 0. {
+                return None;
+            }
+            if overlap < depth {
+                depth = overlap;
+                normal = axis;
+            }
+        }
+
+        if normal.dot(other.centroid() - self.centroid()) < 0. {
+            normal = -normal;
+        }
+
+        let inside: Vec<Vec2> = self
+            .vertices
+            .iter()
+            .filter(|v| other.contains_point(**v))
+            .chain(other.vertices.iter().filter(|v| self.contains_point(**v)))
+            .copied()
+            .collect();
+        let point = match inside.len() {
+            0 => self.centroid().midpoint(other.centroid()),
+            count => inside.iter().sum::<Vec2>() / count as f32,
+        };
+
+        Some((normal, depth, point))
+    }
+
+    /* The second moment of area about the centroid. Multiplying by the density gives the moment of
+     * inertia of a solid body with this shape.
+     */
+    pub fn second_moment_of_area(&self) -> f32 {
+        let centroid = self.centroid();
+        zip(self.vertices.iter(), self.vertices.iter().cycle().skip(1))
+            .map(|(a, b)| {
+                let (a, b) = (a - centroid, b - centroid);
+                a.perp_dot(b) * (a.dot(a) + a.dot(b) + b.dot(b))
+            })
+            .sum::<f32>()
+            / 12.
+    }
+
     pub fn collides_with(&self, other: &Self) -> bool {
         self.collision_displacement(other).is_some()
     }
+
+    /* The boolean operations below only work on convex polygons, and always produce convex
+     * polygons. Concave shapes should be decomposed first.
+     *
+     * The intersection is found by clipping this polygon against the inside of every edge of the
+     * other one (Sutherland-Hodgman).
+     */
+    pub fn intersection(&self, other: &Self) -> Option<Polygon> {
+        debug_assert!(self.is_convex() && other.is_convex());
+        let clipped = other
+            .edges
+            .iter()
+            .fold(self.vertices.clone(), |remaining, (start, end)| {
+                clip_to_left(&remaining, *start, *end)
+            });
+        Polygon::try_new(clipped).ok()
+    }
+
+    /* Walks the edges of the other polygon, cutting off the part of this polygon that lies outside
+     * each edge as a separate piece. Whatever is left at the end is the intersection, which gets
+     * thrown away.
+     */
+    pub fn difference(&self, other: &Self) -> Vec<Polygon> {
+        debug_assert!(self.is_convex() && other.is_convex());
+        if self.intersection(other).is_none() {
+            return vec![self.clone()];
+        }
+
+        let mut pieces = vec![];
+        let mut remaining = self.vertices.clone();
+        for (start, end) in other.edges.iter() {
+            if let Ok(piece) = Polygon::try_new(clip_to_left(&remaining, *end, *start)) {
+                pieces.push(piece);
+            }
+            remaining = clip_to_left(&remaining, *start, *end);
+            if remaining.len() < 3 {
+                break;
+            }
+        }
+        pieces
+    }
+
+    /* The union of two convex polygons usually is not convex, so it is returned as this polygon
+     * plus the convex pieces of the other one that stick out of it.
+     */
+    pub fn union(&self, other: &Self) -> Vec<Polygon> {
+        let mut pieces = vec![self.clone()];
+        pieces.extend(other.difference(self));
+        pieces
+    }
+}
+
+/* Drops vertices that are the same as the one before them, or that sit on the line between their
+ * neighbours. Removing a vertex can make one of its neighbours degenerate, so this keeps going
+ * until nothing changes.
+ */
+fn remove_degenerate_vertices(mut vertices: Vec<Vec2>) -> Vec<Vec2> {
+    let mut changed = true;
+    while changed && vertices.len() >= 3 {
+        changed = false;
+        let count = vertices.len();
+        for i in 0..count {
+            let previous = vertices[(i + count - 1) % count];
+            let current = vertices[i];
+            let next = vertices[(i + 1) % count];
+            let incoming = current - previous;
+            let outgoing = next - current;
+            let duplicate = incoming.length() < EPSILON;
+            let collinear = incoming.perp_dot(outgoing).abs()
+                <= EPSILON * incoming.length() * outgoing.length();
+            if duplicate || collinear {
+                vertices.remove(i);
+                changed = true;
+                break;
+            }
+        }
+    }
+    vertices
 }
 
-impl Hash for Polygon {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.id.hash(state);
+/* Keeps the part of the polygon that is on the left of the line going from `start` to `end`, which
+ * is the inside of that edge for a counter-clockwise polygon.
+ */
+fn clip_to_left(vertices: &[Vec2], start: Vec2, end: Vec2) -> Vec<Vec2> {
+    let side = |point: Vec2| (end - start).perp_dot(point - start);
+    let mut clipped = vec![];
+    for (current, next) in zip(vertices.iter(), vertices.iter().cycle().skip(1)) {
+        let (current_side, next_side) = (side(*current), side(*next));
+        if current_side >= 0. {
+            clipped.push(*current);
+        }
+        if (current_side >= 0.) != (next_side >= 0.) {
+            let t = current_side / (current_side - next_side);
+            clipped.push(current.lerp(*next, t));
+        }
     }
+    clipped
+}
+
+fn project(vertices: &[Vec2], axis: Vec2) -> (f32, f32) {
+    vertices
+        .iter()
+        .map(|v| v.dot(axis))
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), shadow| {
+            (min.min(shadow), max.max(shadow))
+        })
+}
+
+fn edges_of(vertices: &[Vec2]) -> Vec<(Vec2, Vec2)> {
+    zip(
+        vertices.iter().copied(),
+        vertices.iter().copied().cycle().skip(1),
+    )
+    .collect()
+}
+
+/* Shoelace formula. Positive for counter-clockwise winding, negative for clockwise. */
+fn signed_area(vertices: &[Vec2]) -> f32 {
+    zip(vertices.iter(), vertices.iter().cycle().skip(1))
+        .map(|(a, b)| a.perp_dot(*b))
+        .sum::<f32>()
+        * 0.5
 }
 
-impl PartialEq for Polygon {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
+fn segments_intersect((a, b): (Vec2, Vec2), (c, d): (Vec2, Vec2)) -> bool {
+    let d1 = (b - a).perp_dot(c - a);
+    let d2 = (b - a).perp_dot(d - a);
+    let d3 = (d - c).perp_dot(a - c);
+    let d4 = (d - c).perp_dot(b - c);
+    d1 * d2 < 0. && d3 * d4 < 0.
+}
+
+/* Checks every pair of edges that do not share a vertex. This is quadratic, but polygons in the
+ * game only have a handful of vertices.
+ */
+fn is_self_intersecting(vertices: &[Vec2]) -> bool {
+    let count = vertices.len();
+    let edge = |i: usize| (vertices[i], vertices[(i + 1) % count]);
+    (0..count).any(|i| {
+        ((i + 2)..count)
+            .filter(|j| (j + 1) % count != i)
+            .any(|j| segments_intersect(edge(i), edge(j)))
+    })
+}
+
+fn triangle_contains_point(a: Vec2, b: Vec2, c: Vec2, point: Vec2) -> bool {
+    (b - a).perp_dot(point - a) >= 0.
+        && (c - b).perp_dot(point - b) >= 0.
+        && (a - c).perp_dot(point - c) >= 0.
+}
+
+/* Ear clipping triangulation of a simple, counter-clockwise polygon. A vertex is an ear when it is
+ * convex and no other vertex lies within the triangle it forms with its neighbours; such a
+ * triangle can be cut off without affecting the rest of the polygon. The triangles are returned as
+ * indices into `vertices` so they can be merged afterwards.
+ */
+fn triangulate(vertices: &[Vec2]) -> Vec<Vec<usize>> {
+    let mut remaining: Vec<usize> = (0..vertices.len()).collect();
+    let mut triangles = vec![];
+
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|i| {
+            let previous = remaining[(i + count - 1) % count];
+            let current = remaining[*i];
+            let next = remaining[(i + 1) % count];
+            let (a, b, c) = (vertices[previous], vertices[current], vertices[next]);
+            (b - a).perp_dot(c - b) > 0.
+                && !remaining
+                    .iter()
+                    .filter(|r| ![previous, current, next].contains(r))
+                    .any(|r| triangle_contains_point(a, b, c, vertices[*r]))
+        });
+
+        /* Every simple polygon has at least two ears, so this only happens due to floating point
+         * trouble. Clip whatever vertex we are on so we always terminate.
+         */
+        let ear = ear.unwrap_or(0);
+        triangles.push(vec![
+            remaining[(ear + count - 1) % count],
+            remaining[ear],
+            remaining[(ear + 1) % count],
+        ]);
+        remaining.remove(ear);
     }
+    triangles.push(remaining);
+    triangles
 }
 
-impl Eq for Polygon {}
+/* Two parts that share an edge, walked in opposite directions, are glued together along that
+ * edge. The result is only returned if it is still convex.
+ */
+fn merge_convex(lhs: &[usize], rhs: &[usize], vertices: &[Vec2]) -> Option<Vec<usize>> {
+    let lhs_count = lhs.len();
+    let rhs_count = rhs.len();
+    let (i, j) = (0..lhs_count).find_map(|i| {
+        let (a, b) = (lhs[i], lhs[(i + 1) % lhs_count]);
+        (0..rhs_count)
+            .find(|j| rhs[*j] == b && rhs[(j + 1) % rhs_count] == a)
+            .map(|j| (i, j))
+    })?;
+
+    /* Walk the left part starting after the shared edge, then the right part, also starting
+     * after the shared edge. Each shared vertex shows up once.
+     */
+    let mut merged: Vec<usize> = (1..=lhs_count).map(|k| lhs[(i + k) % lhs_count]).collect();
+    merged.extend((2..rhs_count).map(|k| rhs[(j + k) % rhs_count]));
+
+    let count = merged.len();
+    let convex = (0..count).all(|k| {
+        let a = vertices[merged[k]];
+        let b = vertices[merged[(k + 1) % count]];
+        let c = vertices[merged[(k + 2) % count]];
+        (b - a).perp_dot(c - b) >= 0.
+    });
+    convex.then_some(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_winding_is_normalized() {
+        let clockwise = Polygon::new(vec![
+            Vec2::new(0., 1.),
+            Vec2::new(1., 1.),
+            Vec2::new(1., 0.),
+            Vec2::new(0., 0.),
+        ]);
+        assert!(clockwise.area() > 0.);
+        assert!(clockwise.is_convex());
+        assert!(clockwise.contains_point(Vec2::new(0.5, 0.5)));
+    }
+
+    #[test]
+    fn test_degenerate_vertices_are_removed() {
+        let polygon = Polygon::new(vec![
+            Vec2::new(0., 0.),
+            Vec2::new(0., 0.),
+            Vec2::new(0.5, 0.),
+            Vec2::new(1., 0.),
+            Vec2::new(1., 1.),
+        ]);
+        assert_eq!(polygon.vertices().len(), 3);
+    }
+
+    #[test]
+    fn test_invalid_polygons() {
+        let line = Polygon::try_new(vec![Vec2::ZERO, Vec2::X, Vec2::new(2., 0.)]);
+        assert_eq!(line.unwrap_err(), PolygonError::TooFewVertices(2));
+
+        let bowtie = Polygon::try_new(vec![
+            Vec2::new(0., 0.),
+            Vec2::new(1., 1.),
+            Vec2::new(1., 0.),
+            Vec2::new(0., 1.),
+        ]);
+        assert_eq!(bowtie.unwrap_err(), PolygonError::SelfIntersecting);
+
+        let square = Polygon::new_regular(4, 1., Vec2::ZERO, 0.);
+        let text = ron::to_string(&square).unwrap();
+        assert_eq!(ron::from_str::<Polygon>(&text), Ok(square));
+        assert!(ron::from_str::<Polygon>("[]").is_err());
+        assert!(ron::from_str::<Polygon>("[(0., 0.), (1., 1.), (1., 0.), (0., 1.)]").is_err());
+    }
+
+    #[test]
+    fn test_decompose_concave() {
+        let vertices = vec![
+            Vec2::new(0., 0.),
+            Vec2::new(2., 0.),
+            Vec2::new(2., 1.),
+            Vec2::new(1., 1.),
+            Vec2::new(1., 2.),
+            Vec2::new(0., 2.),
+        ];
+        let l_shape = Polygon::new(vertices.clone());
+        assert!(!l_shape.is_convex());
+
+        let parts = l_shape.decompose();
+        assert!(
+            parts.len() >= 2 && parts.len() <= 4,
+            "Got {} parts",
+            parts.len()
+        );
+        assert!(parts.iter().all(|p| p.is_convex()));
+        let area: f32 = parts.iter().map(|p| p.area()).sum();
+        assert!((area - 3.).abs() < 1e-5, "Got area {}", area);
+    }
+
+    #[test]
+    fn test_measurements() {
+        let square = Polygon::new(vec![
+            Vec2::new(1., 1.),
+            Vec2::new(3., 1.),
+            Vec2::new(3., 3.),
+            Vec2::new(1., 3.),
+        ]);
+        assert!((square.area() - 4.).abs() < 1e-5);
+        assert!(square.centroid().distance(Vec2::new(2., 2.)) < 1e-5);
+        assert_eq!(square.aabb(), (Vec2::new(1., 1.), Vec2::new(3., 3.)));
+    }
+
+    #[test]
+    fn test_transform_in_place() {
+        let mut triangle = Polygon::new_triangle(1., Vec2::ZERO, 0.);
+        let area = triangle.area();
+        let centroid = triangle.centroid();
+        let expected_centroid = Vec2::new(-(centroid.x + 5.), centroid.y);
+
+        triangle.translate(Vec2::new(5., 0.));
+        triangle.rotate(std::f32::consts::FRAC_PI_2);
+        triangle.scale(2.);
+        triangle.transform(&Affine2::from_scale(Vec2::new(-1., 1.)));
+
+        assert!(triangle.area() > 0.);
+        assert!((triangle.area() - area * 4.).abs() < 1e-4);
+        assert!(triangle.centroid().distance(expected_centroid) < 1e-4);
+        assert!(
+            triangle
+                .edges()
+                .zip(triangle.vertices())
+                .all(|((start, _), vertex)| start == vertex)
+        );
+        assert!(triangle.contains_point(expected_centroid));
+
+        let before = triangle.clone();
+        let collapse = Affine2::from_scale(Vec2::new(0., 1.));
+        assert!(triangle.try_transform(&collapse).is_err());
+        let nan = Affine2::from_translation(Vec2::NAN);
+        assert_eq!(triangle.try_transform(&nan), Err(PolygonError::NotFinite));
+        assert_eq!(triangle, before);
+
+        let diamond = Polygon::new_regular(4, 1., Vec2::ZERO, std::f32::consts::FRAC_PI_4);
+        let first = diamond.vertices().next().unwrap();
+        assert!(first.distance(Vec2::from_angle(std::f32::consts::FRAC_PI_4)) < 1e-5);
+        assert!((diamond.area() - 2.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_structural_equality() {
+        let triangle = Polygon::new_triangle(1., Vec2::ZERO, 0.);
+        assert_eq!(triangle, triangle.clone());
+        assert_eq!(triangle, Polygon::new_triangle(1., Vec2::ZERO, 0.));
+        assert_ne!(triangle, Polygon::new_triangle(1., Vec2::X, 0.));
+    }
+
+    #[test]
+    fn test_boolean_operations() {
+        let square = |center: Vec2, size: f32| {
+            Polygon::new(vec![
+                center + Vec2::new(-size, -size),
+                center + Vec2::new(size, -size),
+                center + Vec2::new(size, size),
+                center + Vec2::new(-size, size),
+            ])
+        };
+        let big = square(Vec2::ZERO, 1.);
+        let small = square(Vec2::ZERO, 0.5);
+        let corner = square(Vec2::ONE, 0.5);
+        let far = square(Vec2::splat(10.), 0.5);
+        let total_area = |pieces: &[Polygon]| pieces.iter().map(|p| p.area()).sum::<f32>();
+
+        assert!((big.intersection(&corner).unwrap().area() - 0.25).abs() < 1e-5);
+        assert!(big.intersection(&far).is_none());
+
+        let hole = big.difference(&small);
+        assert!(hole.iter().all(|p| p.is_convex()));
+        assert!((total_area(&hole) - 3.).abs() < 1e-5);
+        assert!(hole.iter().all(|p| !p.contains_point(Vec2::ZERO)));
+
+        assert_eq!(big.difference(&far), vec![big.clone()]);
+        assert!((total_area(&big.difference(&corner)) - 3.75).abs() < 1e-5);
+        assert!((total_area(&big.union(&corner)) - 4.75).abs() < 1e-5);
+        assert!((total_area(&big.union(&small)) - 4.).abs() < 1e-5);
+    }
+}
@@ -1,21 +1,31 @@
+use crate::engine::arena::{Arena, Handle};
 use crate::engine::primitives::Polygon;
 use glam::Vec2;
-use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 use std::ops::RangeInclusive;
 
 const MAX_TREE_ENTRIES: usize = 10;
+/* Polygons stacked on top of each other cannot be told apart by splitting further, so children
+ * are never made smaller than this.
+ */
+const MIN_CHILD_SIZE: f32 = 1e-3;
+/* Points are looked up as a tiny polygon around them, small enough to only touch what contains
+ * the point or very nearly does.
+ */
+const PROBE_SIZE: f32 = 1e-4;
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 enum Body {
-    Elements(Vec<usize>),
+    Elements(Vec<Handle>),
     Children(Box<[QuadTreeInner; 4]>),
 }
 
 /* This inner struct for a quadtree will keep track of the range of area for which it is
- * responsible as well as either all the polygon IDs the area contains or the children quadtrees
+ * responsible as well as either all the polygon handles the area contains or the children quadtrees
  * that divide the area.
  */
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct QuadTreeInner {
     body: Body,
     x_range: RangeInclusive<f32>,
@@ -37,36 +47,23 @@ impl QuadTreeInner {
         QuadTreeInner::new_with_ranges(f32::MIN..=f32::MAX, f32::MIN..=f32::MAX)
     }
 
-    pub fn remove_from_point(&mut self, point: Vec2, store: &HashMap<usize, Polygon>) {
-        if self.contains_point(&point) {
-            match &mut self.body {
-                Body::Elements(elements) => {
-                    elements.retain(|id| {
-                        if let Some(polygon) = store.get(id) {
-                            !polygon.contains_point(point)
-                        } else {
-                            false
-                        }
-                    });
-                }
-                Body::Children(children) => {
-                    children
-                        .iter_mut()
-                        .for_each(|c| c.remove_from_point(point, store));
-                }
-            }
-        }
-    }
-
-    pub fn insert(&mut self, polygon: &Polygon, store: &HashMap<usize, Polygon>) {
-        if polygon.vertices().any(|v| self.contains_point(v)) {
+    /* A polygon goes into every child its bounding box overlaps, not only the ones holding one of
+     * its vertices, as points and areas in the middle of it have to find it too.
+     */
+    pub fn insert(&mut self, handle: Handle, store: &Arena<Polygon>) {
+        let Some(polygon) = store.get(handle) else {
+            return;
+        };
+        if self.overlaps(polygon.aabb()) {
             match &mut self.body {
                 Body::Elements(elements) => {
-                    elements.push(polygon.id());
+                    elements.push(handle);
                     /* If inserting this would exceed the number of allowed entries, split this
                      * into four children.
                      */
-                    if elements.len() > MAX_TREE_ENTRIES {
+                    let size = (self.x_range.end() - self.x_range.start())
+                        .min(self.y_range.end() - self.y_range.start());
+                    if elements.len() > MAX_TREE_ENTRIES && size > MIN_CHILD_SIZE * 2. {
                         let mid_x = self.x_range.end().midpoint(*self.x_range.start());
                         let mid_y = self.y_range.end().midpoint(*self.y_range.start());
                         let mut children = [
@@ -87,60 +84,86 @@ impl QuadTreeInner {
                                 *self.y_range.start()..=mid_y,
                             ),
                         ];
-                        /* Reinsert all of the known polygon IDs into the new children */
-                        elements.iter().flat_map(|id| store.get(id)).for_each(|p| {
-                            children.iter_mut().for_each(|c| c.insert(p, store));
+                        /* Reinsert all of the known polygon handles into the new children */
+                        elements.iter().for_each(|h| {
+                            children.iter_mut().for_each(|c| c.insert(*h, store));
                         });
 
                         self.body = Body::Children(Box::new(children));
                     }
                 }
                 Body::Children(children) => {
-                    children.iter_mut().for_each(|c| c.insert(polygon, store));
+                    children.iter_mut().for_each(|c| c.insert(handle, store));
+                }
+            }
+        }
+    }
+
+    /* Only the children overlapping the bounding box of the polygon can hold its handle. */
+    pub fn remove(&mut self, handle: Handle, aabb: (Vec2, Vec2)) {
+        if self.overlaps(aabb) {
+            match &mut self.body {
+                Body::Elements(elements) => elements.retain(|h| *h != handle),
+                Body::Children(children) => {
+                    children.iter_mut().for_each(|c| c.remove(handle, aabb))
                 }
             }
         }
     }
 
-    fn contains_point(&self, point: &Vec2) -> bool {
-        self.x_range.contains(&point.x) && self.y_range.contains(&point.y)
+    fn overlaps(&self, (min, max): (Vec2, Vec2)) -> bool {
+        min.x <= *self.x_range.end()
+            && max.x >= *self.x_range.start()
+            && min.y <= *self.y_range.end()
+            && max.y >= *self.y_range.start()
     }
 
-    fn find_in_area<'a>(
-        &self,
-        area: &Polygon,
-        store: &'a HashMap<usize, Polygon>,
-    ) -> HashSet<&'a Polygon> {
-        if area.vertices().any(|vertex| self.contains_point(vertex)) {
+    /* Areas can be larger than the children, for example when carving a big hole, so this checks
+     * the bounding box of the area rather than only its vertices.
+     */
+    fn find_in_area(&self, area: &Polygon, store: &Arena<Polygon>) -> BTreeSet<Handle> {
+        if self.overlaps(area.aabb()) {
             match &self.body {
                 Body::Elements(elements) => elements
                     .iter()
-                    .flat_map(|id| store.get(id))
-                    .filter(|polygon| polygon.collides_with(area))
-                    .collect::<HashSet<&Polygon>>(),
+                    .copied()
+                    .filter(|handle| {
+                        store
+                            .get(*handle)
+                            .is_some_and(|polygon| polygon.collides_with(area))
+                    })
+                    .collect::<BTreeSet<Handle>>(),
                 Body::Children(children) => children
                     .iter()
                     .flat_map(|child| child.find_in_area(area, store))
-                    .collect::<HashSet<&Polygon>>(),
+                    .collect::<BTreeSet<Handle>>(),
             }
         } else {
-            HashSet::new()
+            BTreeSet::new()
         }
     }
 }
 
+impl Default for QuadTreeInner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct QuadTree {
-    store: HashMap<usize, Polygon>,
+    store: Arena<Polygon>,
     root: QuadTreeInner,
 }
 
 /* This quadtree implementation keeps a master store of all polygons inserted, letting the inner
- * quadtree struct keep track of only the polygon IDs.
+ * quadtree struct keep track of only the polygon handles. The handles are issued by the store, so
+ * two quadtrees built from the same sequence of operations hand out the same handles.
  */
 impl QuadTree {
     pub fn new() -> Self {
         Self {
-            store: HashMap::new(),
+            store: Arena::new(),
             root: QuadTreeInner::new(),
         }
     }
@@ -149,27 +172,112 @@ impl QuadTree {
         self.store.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &Polygon> {
         self.store.values()
     }
 
-    pub fn insert(&mut self, polygon: Polygon) {
-        let id = polygon.id();
-        self.store.insert(polygon.id(), polygon);
-        let polygon = self.store.get(&id).unwrap();
-        self.root.insert(polygon, &self.store);
+    pub fn iter_with_handles(&self) -> impl Iterator<Item = (Handle, &Polygon)> {
+        self.store.iter()
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&Polygon> {
+        self.store.get(handle)
+    }
+
+    /* Collision checks only work on convex polygons, so concave ones are stored as their convex
+     * parts. Returns the handles of everything that got stored.
+     */
+    pub fn insert(&mut self, polygon: Polygon) -> Vec<Handle> {
+        if !polygon.is_convex() {
+            return polygon
+                .decompose()
+                .into_iter()
+                .flat_map(|part| self.insert(part))
+                .collect();
+        }
+        let handle = self.store.insert(polygon);
+        self.root.insert(handle, &self.store);
+        vec![handle]
+    }
+
+    /* Removes every polygon containing the point, handing them back in handle order. */
+    pub fn remove_from_point(&mut self, point: Vec2) -> Vec<Polygon> {
+        let probe = Polygon::new_regular(6, PROBE_SIZE, point, 0.);
+        let removed: Vec<Handle> = self
+            .find_handles_in_area(&probe)
+            .filter(|handle| {
+                self.store
+                    .get(*handle)
+                    .is_some_and(|p| p.contains_point(point))
+            })
+            .collect();
+        removed
+            .into_iter()
+            .flat_map(|handle| self.remove(handle))
+            .collect()
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Option<Polygon> {
+        let polygon = self.store.get(handle)?;
+        self.root.remove(handle, polygon.aabb());
+        self.store.remove(handle)
     }
 
-    pub fn remove_from_point(&mut self, point: Vec2) {
-        self.root.remove_from_point(point, &self.store);
-        self.store.retain(|_, p| !p.contains_point(point));
+    /* Cuts the given shape out of everything it overlaps. Each overlapped polygon is replaced by
+     * the convex fragments that remain. Returns the handle of the polygon every fragment was cut
+     * from along with the handle of the fragment, in the order the cuts were made. Polygons cut
+     * away altogether leave no entry.
+     */
+    pub fn carve(&mut self, shape: &Polygon) -> Vec<(Handle, Handle)> {
+        if !shape.is_convex() {
+            return shape
+                .decompose()
+                .iter()
+                .flat_map(|part| self.carve(part))
+                .collect();
+        }
+
+        let overlapped: Vec<Handle> = self
+            .find_handles_in_area(shape)
+            .filter(|handle| {
+                self.store
+                    .get(*handle)
+                    .is_some_and(|polygon| polygon.intersection(shape).is_some())
+            })
+            .collect();
+
+        let mut fragments = vec![];
+        for handle in overlapped {
+            if let Some(polygon) = self.remove(handle) {
+                for fragment in polygon.difference(shape) {
+                    fragments.extend(self.insert(fragment).into_iter().map(|f| (handle, f)));
+                }
+            }
+        }
+        fragments
     }
 
+    /* Results come back in handle order, so iterating them is deterministic. */
     pub fn find_in_area(&self, area: &Polygon) -> impl Iterator<Item = &Polygon> {
+        self.find_handles_in_area(area)
+            .flat_map(|handle| self.store.get(handle))
+    }
+
+    pub fn find_handles_in_area(&self, area: &Polygon) -> impl Iterator<Item = Handle> {
         self.root.find_in_area(area, &self.store).into_iter()
     }
 }
 
+impl Default for QuadTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -200,7 +308,7 @@ mod tests {
                     result.len(),
                     1,
                     "Failed with polygon {} and vertex {} and area {}",
-                    polygon.id(),
+                    polygon,
                     vertex,
                     area
                 );
@@ -225,4 +333,53 @@ mod tests {
         let result = qt.find_in_area(&area).collect::<Vec<&Polygon>>();
         assert_eq!(result.len(), 0);
     }
+
+    #[test]
+    fn test_stacked_polygons() {
+        let mut qt = QuadTree::new();
+        for _ in 0..=MAX_TREE_ENTRIES * 2 {
+            qt.insert(Polygon::new_triangle(1., Vec2::new(3., 3.), 0.));
+        }
+        let area = Polygon::new_triangle(0.1, Vec2::new(3., 3.), 0.);
+        assert_eq!(qt.find_in_area(&area).count(), MAX_TREE_ENTRIES * 2 + 1);
+    }
+
+    #[test]
+    fn test_deterministic_handles() {
+        let build = || {
+            let mut qt = QuadTree::new();
+            for c in 0..=MAX_TREE_ENTRIES * 2 {
+                qt.insert(Polygon::new_triangle(1., Vec2::new(2. * c as f32, 0.), 0.));
+            }
+            qt.remove_from_point(Vec2::new(4., 0.));
+            qt.insert(Polygon::new_triangle(1., Vec2::new(4., 5.), 0.));
+            qt.iter_with_handles()
+                .map(|(handle, polygon)| (handle, polygon.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(build(), build());
+    }
+
+    #[test]
+    fn test_carve() {
+        let mut qt = QuadTree::new();
+        for c in 0..=MAX_TREE_ENTRIES {
+            qt.insert(Polygon::new_triangle(1., Vec2::new(c as f32, 0.), 0.));
+        }
+        let area_before: f32 = qt.iter().map(|p| p.area()).sum();
+
+        let hole = Polygon::new_regular(8, 0.2, Vec2::new(3., 0.), 0.);
+        let fragments = qt.carve(&hole);
+        assert!(!fragments.is_empty());
+        assert!(fragments.iter().all(|(_, h)| qt.get(*h).is_some()));
+
+        let area_after: f32 = qt.iter().map(|p| p.area()).sum();
+        assert!((area_before - hole.area() - area_after).abs() < 1e-4);
+        assert_eq!(
+            qt.find_in_area(&hole)
+                .filter(|p| p.intersection(&hole).is_some())
+                .count(),
+            0
+        );
+    }
 }
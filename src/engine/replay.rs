@@ -0,0 +1,117 @@
+use crate::engine::game::Game;
+use crate::engine::save::{self, SaveError};
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/* Bumped whenever the layout of `Replay` changes. The game it starts from is a save, so replays
+ * also stop working whenever `SAVE_VERSION` is bumped.
+ */
+pub const REPLAY_VERSION: u32 = 1;
+/* Replay files start with this, so they can be told apart from saves and levels. */
+const MAGIC: &[u8; 4] = b"RRRP";
+
+/* A game as it was when the recording started, followed by the movement of every tick after.
+ * Ticks spent generating a level do not read the movement at all, and how many of those there
+ * are depends on how fast the machine is, so only the ticks the game was ready for are recorded.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    start: Vec<u8>,
+    inputs: Vec<Vec2>,
+    #[serde(skip)]
+    played: usize,
+}
+
+impl Replay {
+    pub fn new(game: &Game) -> Result<Self, SaveError> {
+        Ok(Self {
+            start: game.save()?,
+            inputs: vec![],
+            played: 0,
+        })
+    }
+
+    /* The game the recording starts from, to be ticked with the inputs from `play`. */
+    pub fn start(&self) -> Result<Game, SaveError> {
+        Game::restore(&self.start)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /* Records the movement the game is about to be ticked with. */
+    pub fn record(&mut self, game: &Game, movement: Vec2) {
+        if game.is_ready() {
+            self.inputs.push(movement);
+        }
+    }
+
+    /* The movement to tick the game with next, or `None` once everything has been played. */
+    pub fn play(&mut self, game: &Game) -> Option<Vec2> {
+        if !game.is_ready() {
+            return (!self.is_finished()).then_some(Vec2::ZERO);
+        }
+        let movement = self.inputs.get(self.played).copied();
+        if movement.is_some() {
+            self.played += 1;
+        }
+        movement
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.played >= self.inputs.len()
+    }
+
+    /* Framed like a save, with magic bytes of its own. */
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SaveError> {
+        save::frame(MAGIC, REPLAY_VERSION, self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SaveError> {
+        save::unframe(MAGIC, REPLAY_VERSION, bytes)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SaveError> {
+        std::fs::write(path, self.to_bytes()?).map_err(SaveError::Io)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, SaveError> {
+        Self::from_bytes(&std::fs::read(path).map_err(SaveError::Io)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_plays_back_the_same_game() {
+        let mut game = Game::new_with_seed(0, 3);
+        let mut recording = Replay::new(&game).unwrap();
+        let mut ready_ticks = 0;
+        while ready_ticks < 150 {
+            let movement = Vec2::from_angle(ready_ticks as f32 * 0.07);
+            recording.record(&game, movement);
+            ready_ticks += game.is_ready() as usize;
+            (game, _, _) = game.tick(&movement, vec![], vec![]);
+        }
+        assert_eq!(recording.len(), 150);
+
+        let mut replay = Replay::from_bytes(&recording.to_bytes().unwrap()).unwrap();
+        let mut replayed = replay.start().unwrap();
+        while let Some(movement) = replay.play(&replayed) {
+            (replayed, _, _) = replayed.tick(&movement, vec![], vec![]);
+        }
+        assert_eq!(replayed.save().unwrap(), game.save().unwrap());
+        assert!(matches!(
+            Replay::from_bytes(&game.save().unwrap()),
+            Err(SaveError::WrongKind)
+        ));
+    }
+}
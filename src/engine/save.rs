@@ -0,0 +1,140 @@
+use crate::engine::game::Game;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::path::Path;
+
+/* Bumped whenever anything saved as part of a `Game` changes shape. Saves are a snapshot of the
+ * whole game, down to the random number generator, so they do not survive such changes.
+ */
+pub const SAVE_VERSION: u32 = 1;
+/* Save files start with this, so they can be told apart from level files. */
+const MAGIC: &[u8; 4] = b"RRSV";
+
+/* Everything that can go wrong writing or reading a save, or anything else framed like one. */
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Decode(bincode::error::DecodeError),
+    Encode(bincode::error::EncodeError),
+    /* The file does not start with the magic bytes expected, so it is some other kind of file. */
+    WrongKind,
+    UnsupportedVersion { found: u32, expected: u32 },
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "Could not access the file: {}", e),
+            SaveError::Decode(e) => write!(f, "Could not read the file: {}", e),
+            SaveError::Encode(e) => write!(f, "Could not write the file: {}", e),
+            SaveError::WrongKind => write!(f, "This is not the right kind of file"),
+            SaveError::UnsupportedVersion { found, expected } => write!(
+                f,
+                "File version {} is not supported, expected {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+/* Writes the magic bytes, the version and then the value itself. The version is read on its own
+ * first by `unframe`, so a file from another version is refused rather than misread.
+ */
+pub fn frame<T: Serialize>(magic: &[u8; 4], version: u32, value: &T) -> Result<Vec<u8>, SaveError> {
+    let config = bincode::config::standard();
+    let mut bytes = magic.to_vec();
+    bytes.extend(bincode::encode_to_vec(version, config).map_err(SaveError::Encode)?);
+    bytes.extend(bincode::serde::encode_to_vec(value, config).map_err(SaveError::Encode)?);
+    Ok(bytes)
+}
+
+pub fn unframe<T: DeserializeOwned>(
+    magic: &[u8; 4],
+    version: u32,
+    bytes: &[u8],
+) -> Result<T, SaveError> {
+    let config = bincode::config::standard();
+    let body = bytes.strip_prefix(magic).ok_or(SaveError::WrongKind)?;
+    let (found, read): (u32, usize) =
+        bincode::decode_from_slice(body, config).map_err(SaveError::Decode)?;
+    if found != version {
+        return Err(SaveError::UnsupportedVersion {
+            found,
+            expected: version,
+        });
+    }
+    let (value, _) =
+        bincode::serde::decode_from_slice(&body[read..], config).map_err(SaveError::Decode)?;
+    Ok(value)
+}
+
+impl Game {
+    pub fn save(&self) -> Result<Vec<u8>, SaveError> {
+        frame(MAGIC, SAVE_VERSION, self)
+    }
+
+    pub fn restore(bytes: &[u8]) -> Result<Self, SaveError> {
+        unframe(MAGIC, SAVE_VERSION, bytes)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<(), SaveError> {
+        std::fs::write(path, self.save()?).map_err(SaveError::Io)
+    }
+
+    pub fn restore_from(path: &Path) -> Result<Self, SaveError> {
+        Self::restore(&std::fs::read(path).map_err(SaveError::Io)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::game::Command;
+    use crate::engine::triggers::Event;
+    use glam::Vec2;
+
+    fn tick(game: Game, movement: Vec2) -> (Game, Vec<Command>, Vec<Event>) {
+        game.tick(&movement, vec![], vec![])
+    }
+
+    #[test]
+    fn test_restored_game_ticks_identically() {
+        let mut game = Game::new_with_seed(0, 7);
+        while !game.is_ready() {
+            (game, _, _) = tick(game, Vec2::ZERO);
+        }
+        for t in 0..60 {
+            (game, _, _) = tick(game, Vec2::from_angle(t as f32 * 0.1));
+        }
+
+        let mut restored = Game::restore(&game.save().unwrap()).unwrap();
+        for t in 0..120 {
+            let movement = Vec2::from_angle(t as f32 * -0.05);
+            let (next, commands, events) = tick(game, movement);
+            let (next_restored, restored_commands, restored_events) = tick(restored, movement);
+            assert_eq!(commands, restored_commands, "Diverged on tick {}", t);
+            assert_eq!(events, restored_events, "Diverged on tick {}", t);
+            (game, restored) = (next, next_restored);
+        }
+        assert_eq!(game.save().unwrap(), restored.save().unwrap());
+    }
+
+    #[test]
+    fn test_refuses_other_files() {
+        assert!(matches!(
+            Game::restore(b"RRLV level"),
+            Err(SaveError::WrongKind)
+        ));
+
+        let mut bytes = MAGIC.to_vec();
+        bytes
+            .extend(bincode::encode_to_vec(SAVE_VERSION + 1, bincode::config::standard()).unwrap());
+        assert!(matches!(
+            Game::restore(&bytes),
+            Err(SaveError::UnsupportedVersion { .. })
+        ));
+    }
+}
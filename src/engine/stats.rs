@@ -0,0 +1,88 @@
+use crate::engine::world::{TileKind, World};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/* Numbers describing a generated world, for tuning the generator without playing every level. */
+#[derive(Clone, Debug, Serialize)]
+pub struct WorldStats {
+    pub seed: u64,
+    pub tiles: usize,
+    pub tiles_by_kind: BTreeMap<TileKind, usize>,
+    pub open_cells: usize,
+    /* The fraction of the whole world, tiles and open cells, that is open. */
+    pub open_ratio: f32,
+    /* How many separate groups of connected open cells there are. */
+    pub components: usize,
+    /* The open area that can be reached from the spawn, and that as a fraction of all of it. */
+    pub reachable_area: f32,
+    pub reachable_ratio: f32,
+    pub exit_reachable: bool,
+    pub generation_ms: f64,
+}
+
+impl WorldStats {
+    pub fn measure(world: &World, seed: u64, generation_time: Duration) -> Self {
+        let mut tiles_by_kind: BTreeMap<TileKind, usize> =
+            TileKind::ALL.iter().map(|kind| (*kind, 0)).collect();
+        let mut tile_area = 0.;
+        for (tile, kind) in world.tiles() {
+            *tiles_by_kind.entry(kind).or_default() += 1;
+            tile_area += tile.area();
+        }
+
+        let cells = world.cells();
+        let open_area: f32 = cells.iter().map(|cell| cell.area()).sum();
+        let (labels, components) = cells.components();
+        let spawn = cells.cell_at(world.spawn()).map(|cell| labels[cell]);
+        let reachable_area: f32 = cells
+            .iter()
+            .zip(labels.iter())
+            .filter(|(_, label)| Some(**label) == spawn)
+            .map(|(cell, _)| cell.area())
+            .sum();
+        let exit_reachable = world
+            .exit()
+            .and_then(|exit| cells.cell_at(exit.centroid()))
+            .is_some_and(|exit| Some(labels[exit]) == spawn);
+
+        Self {
+            seed,
+            tiles: tiles_by_kind.values().sum(),
+            tiles_by_kind,
+            open_cells: cells.len(),
+            open_ratio: ratio(open_area, open_area + tile_area),
+            components,
+            reachable_area,
+            reachable_ratio: ratio(reachable_area, open_area),
+            exit_reachable,
+            generation_ms: generation_time.as_secs_f64() * 1000.,
+        }
+    }
+}
+
+fn ratio(part: f32, whole: f32) -> f32 {
+    match whole > 0. {
+        true => part / whole,
+        false => 0.,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::level::LevelFile;
+
+    #[test]
+    fn test_measure_hand_made_level() {
+        let level = LevelFile::from_ron(include_str!("../../levels/tutorial.ron")).unwrap();
+        let world = World::from_level(&level).unwrap();
+        let stats = WorldStats::measure(&world, 0, Duration::ZERO);
+        assert_eq!(stats.tiles, 5);
+        assert_eq!(stats.open_cells, 4);
+        assert_eq!(stats.components, 1);
+        assert!(stats.exit_reachable);
+        assert!((stats.reachable_ratio - 1.).abs() < 1e-5);
+        assert!(stats.open_ratio > 0. && stats.open_ratio < 1.);
+    }
+}
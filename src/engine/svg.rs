@@ -0,0 +1,230 @@
+use crate::engine::entities::{COLLECTIBLE_COLOUR, ENEMY_COLOUR};
+use crate::engine::game::{Command, EXIT_UNLOCKED_COLOUR, Stroke, outline};
+use crate::engine::world::World;
+use glam::{Vec2, Vec3};
+use std::fmt::Write;
+
+const BACKGROUND_COLOUR: Vec3 = Vec3::ZERO;
+pub const CELL_COLOUR: Vec3 = Vec3::new(0.08, 0.08, 0.1);
+pub const SPAWN_COLOUR: Vec3 = Vec3::new(0.3, 0.6, 1.);
+/* Spawn points, which have no shape of their own, are drawn as circles this big. */
+pub const MARKER_RADIUS: f32 = 0.03;
+
+/* `#rrggbb` for a colour with components from 0 to 1. */
+fn colour(colour: Vec3) -> String {
+    let [r, g, b] = (colour.clamp(Vec3::ZERO, Vec3::ONE) * 255.)
+        .round()
+        .to_array()
+        .map(|c| c as u8);
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/* Numbers are rounded to a fixed precision, so the same picture always gives the same text no
+ * matter the floating point noise, and documents can be compared line by line.
+ */
+fn number(n: f32) -> String {
+    let text = format!("{:.4}", n);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => "0".to_string(),
+        text => text.to_string(),
+    }
+}
+
+/* The game has y pointing up and SVG has it pointing down, so every point is flipped. */
+fn points<'a>(vertices: impl Iterator<Item = &'a Vec2>) -> String {
+    vertices
+        .map(|v| format!("{},{}", number(v.x), number(-v.y)))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/* SVG centres strokes on the edge rather than drawing them just inside it like the game, so
+ * outlines reach half their width past the shape.
+ */
+fn stroke(stroke: Option<Stroke>) -> String {
+    match stroke {
+        Some((stroke, width)) => format!(
+            r#" stroke="{}" stroke-width="{}" stroke-linejoin="miter""#,
+            colour(stroke),
+            number(width)
+        ),
+        None => String::new(),
+    }
+}
+
+fn polygon<'a>(
+    svg: &mut String,
+    vertices: impl Iterator<Item = &'a Vec2>,
+    fill: Vec3,
+    outline: Option<Stroke>,
+) {
+    let _ = writeln!(
+        svg,
+        r#"<polygon points="{}" fill="{}"{}/>"#,
+        points(vertices),
+        colour(fill),
+        stroke(outline)
+    );
+}
+
+fn circle(svg: &mut String, center: Vec2, radius: f32, fill: Vec3, outline: Option<Stroke>) {
+    let _ = writeln!(
+        svg,
+        r#"<circle cx="{}" cy="{}" r="{}" fill="{}"{}/>"#,
+        number(center.x),
+        number(-center.y),
+        number(radius),
+        colour(fill),
+        stroke(outline)
+    );
+}
+
+/* Starts a document showing the given area, filled with a colour. */
+fn document(min: Vec2, max: Vec2, background: Vec3) -> String {
+    let (x, y, width, height) = (
+        number(min.x),
+        number(-max.y),
+        number(max.x - min.x),
+        number(max.y - min.y),
+    );
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+        x, y, width, height
+    );
+    let _ = writeln!(
+        svg,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+        x,
+        y,
+        width,
+        height,
+        colour(background)
+    );
+    svg
+}
+
+/* A frame as drawn from the commands of a tick. Commands are in screen coordinates, where the
+ * window shows at least the square from -1 to 1, so that is the area the document shows. A clear
+ * paints over everything drawn before it, so only what comes after the last one is kept.
+ */
+pub fn commands_to_svg(commands: &[Command]) -> String {
+    let last_clear = commands
+        .iter()
+        .rposition(|command| matches!(command, Command::Clear(_)));
+    let background = match last_clear.map(|index| &commands[index]) {
+        Some(Command::Clear(colour)) => *colour,
+        _ => BACKGROUND_COLOUR,
+    };
+
+    let mut svg = document(Vec2::NEG_ONE, Vec2::ONE, background);
+    for command in commands[last_clear.map_or(0, |index| index + 1)..].iter() {
+        match command {
+            Command::Clear(_) => {}
+            Command::RenderCircle((center, radius, fill, outline)) => {
+                circle(&mut svg, *center, *radius, *fill, *outline)
+            }
+            Command::RenderFilledPolygon((vertices, fill, outline)) => {
+                polygon(&mut svg, vertices.iter(), *fill, *outline)
+            }
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/* A map of the whole world: its open cells, exit and tiles, with the spawn, collectible and
+ * enemy spots marked on top.
+ */
+pub fn world_to_svg(world: &World) -> String {
+    let (min, max) = world
+        .iter()
+        .chain(world.cells().iter())
+        .map(|polygon| polygon.aabb())
+        .fold((Vec2::MAX, Vec2::MIN), |(min, max), (low, high)| {
+            (min.min(low), max.max(high))
+        });
+    let (min, max) = match min.cmple(max).all() {
+        true => (min, max),
+        false => (Vec2::NEG_ONE, Vec2::ONE),
+    };
+
+    let mut svg = document(min, max, BACKGROUND_COLOUR);
+    for cell in world.cells().iter() {
+        polygon(&mut svg, cell.vertices(), CELL_COLOUR, None);
+    }
+    if let Some(exit) = world.exit() {
+        polygon(&mut svg, exit.vertices(), EXIT_UNLOCKED_COLOUR, None);
+    }
+    for (tile, kind) in world.tiles() {
+        polygon(
+            &mut svg,
+            tile.vertices(),
+            kind.colour(),
+            outline(kind.colour()),
+        );
+    }
+    for spot in world.collectible_spawns() {
+        circle(&mut svg, *spot, MARKER_RADIUS, COLLECTIBLE_COLOUR, None);
+    }
+    for spot in world.enemy_spawns() {
+        circle(&mut svg, *spot, MARKER_RADIUS, ENEMY_COLOUR, None);
+    }
+    circle(&mut svg, world.spawn(), MARKER_RADIUS, SPAWN_COLOUR, None);
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::level::LevelFile;
+
+    #[test]
+    fn test_commands_to_svg() {
+        let commands = vec![
+            Command::RenderCircle((Vec2::ZERO, 1., Vec3::ONE, None)),
+            Command::Clear(Vec3::new(0., 0., 1.)),
+            Command::RenderFilledPolygon((
+                vec![
+                    Vec2::new(0., 0.5),
+                    Vec2::new(-0.5, -0.25),
+                    Vec2::new(0.5, -0.25),
+                ],
+                Vec3::new(1., 0.5, 0.),
+                Some((Vec3::ZERO, 0.01)),
+            )),
+            Command::RenderCircle((Vec2::new(0.1, 0.2), 0.05, Vec3::ONE, None)),
+        ];
+        assert_eq!(
+            commands_to_svg(&commands),
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="-1 -1 2 2">"#,
+                "\n",
+                r##"<rect x="-1" y="-1" width="2" height="2" fill="#0000ff"/>"##,
+                "\n",
+                concat!(
+                    r##"<polygon points="0,-0.5 -0.5,0.25 0.5,0.25" fill="#ff8000""##,
+                    r##" stroke="#000000" stroke-width="0.01" stroke-linejoin="miter"/>"##,
+                ),
+                "\n",
+                r##"<circle cx="0.1" cy="-0.2" r="0.05" fill="#ffffff"/>"##,
+                "\n",
+                "</svg>\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_world_to_svg() {
+        let level = LevelFile::from_ron(include_str!("../../levels/tutorial.ron")).unwrap();
+        let svg = world_to_svg(&World::from_level(&level).unwrap());
+        /* Four cells, the exit drawn over one of them and five tiles. */
+        assert_eq!(svg.matches("<polygon").count(), 10);
+        /* Two collectibles and the spawn. */
+        assert_eq!(svg.matches("<circle").count(), 3);
+        assert!(svg.contains(r#"viewBox="-0.3 -0.2 1.8 0.4""#));
+    }
+}
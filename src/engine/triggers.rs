@@ -0,0 +1,168 @@
+use crate::engine::arena::{Arena, Handle};
+use crate::engine::primitives::Polygon;
+use crate::engine::quadtree::QuadTree;
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/* Something that happens in the game because of a trigger. The game carries out the events it
+ * knows how to and then hands all of them to the host, which can react to them as well, like
+ * showing messages.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Event {
+    /* Takes the tiles of a door out of the world. */
+    OpenDoor(Vec<Handle>),
+    SpawnEnemies(Vec<Vec2>),
+    ShowMessage(String),
+}
+
+/* A region of the world that fires events when the player enters or leaves it. A trigger that
+ * only fires `once` goes away as soon as it is entered.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Trigger {
+    pub on_enter: Vec<Event>,
+    pub on_exit: Vec<Event>,
+    pub once: bool,
+}
+
+/* The triggers of a world. Their regions are kept in their own spatial index, apart from the
+ * tiles, so they never block anything. Concave regions get split up by the index, so each part
+ * maps back to the trigger it belongs to.
+ */
+#[derive(Default, Serialize, Deserialize)]
+pub struct Triggers {
+    triggers: Arena<Trigger>,
+    regions: QuadTree,
+    owners: BTreeMap<Handle, Handle>,
+    occupied: BTreeSet<Handle>,
+}
+
+impl Triggers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.triggers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    pub fn insert(&mut self, region: Polygon, trigger: Trigger) -> Handle {
+        self.insert_regions(vec![region], trigger)
+    }
+
+    /* Adds a trigger covering several regions at once. */
+    pub fn insert_regions(&mut self, regions: Vec<Polygon>, trigger: Trigger) -> Handle {
+        let handle = self.triggers.insert(trigger);
+        for region in regions {
+            for part in self.regions.insert(region) {
+                self.owners.insert(part, handle);
+            }
+        }
+        handle
+    }
+
+    /* Every trigger along with the convex parts of its regions. */
+    pub fn iter(&self) -> impl Iterator<Item = (&Trigger, Vec<&Polygon>)> {
+        self.triggers.iter().map(|(handle, trigger)| {
+            let regions = self
+                .owners
+                .iter()
+                .filter(|(_, owner)| **owner == handle)
+                .flat_map(|(part, _)| self.regions.get(*part))
+                .collect();
+            (trigger, regions)
+        })
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Option<Trigger> {
+        let parts: Vec<Handle> = self
+            .owners
+            .iter()
+            .filter(|(_, owner)| **owner == handle)
+            .map(|(part, _)| *part)
+            .collect();
+        for part in parts {
+            self.owners.remove(&part);
+            self.regions.remove(part);
+        }
+        self.occupied.remove(&handle);
+        self.triggers.remove(handle)
+    }
+
+    /* Moves the player to the given area, returning the events of every trigger it entered or
+     * left since the last update, in trigger order.
+     */
+    pub fn update(&mut self, area: &Polygon) -> Vec<Event> {
+        let inside: BTreeSet<Handle> = self
+            .regions
+            .find_handles_in_area(area)
+            .flat_map(|part| self.owners.get(&part).copied())
+            .collect();
+
+        let mut events = vec![];
+        for left in self.occupied.difference(&inside) {
+            if let Some(trigger) = self.triggers.get(*left) {
+                events.extend(trigger.on_exit.iter().cloned());
+            }
+        }
+        let mut spent = vec![];
+        for entered in inside.difference(&self.occupied) {
+            if let Some(trigger) = self.triggers.get(*entered) {
+                events.extend(trigger.on_enter.iter().cloned());
+                if trigger.once {
+                    spent.push(*entered);
+                }
+            }
+        }
+
+        self.occupied = inside;
+        for handle in spent {
+            self.remove(handle);
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enter_and_exit() {
+        let mut triggers = Triggers::new();
+        let message = |text: &str| Event::ShowMessage(text.to_string());
+        triggers.insert(
+            Polygon::new_regular(6, 1., Vec2::ZERO, 0.),
+            Trigger {
+                on_enter: vec![message("in")],
+                on_exit: vec![message("out")],
+                once: false,
+            },
+        );
+        triggers.insert(
+            Polygon::new_regular(6, 1., Vec2::new(5., 0.), 0.),
+            Trigger {
+                on_enter: vec![message("once")],
+                on_exit: vec![],
+                once: true,
+            },
+        );
+        let at = |x: f32| Polygon::new_regular(6, 0.1, Vec2::new(x, 0.), 0.);
+
+        assert_eq!(triggers.update(&at(0.)), vec![message("in")]);
+        assert_eq!(triggers.update(&at(0.2)), vec![]);
+        assert_eq!(
+            triggers.update(&at(5.)),
+            vec![message("out"), message("once")]
+        );
+        assert_eq!(triggers.len(), 1);
+        assert_eq!(triggers.update(&at(2.5)), vec![]);
+        assert_eq!(triggers.update(&at(5.)), vec![]);
+    }
+}
@@ -1,36 +1,622 @@
+use crate::engine::arena::{Arena, Handle};
+use crate::engine::cells::Cells;
+use crate::engine::entities::Stone;
+use crate::engine::level::{LEVEL_VERSION, LevelError, LevelFile, TileData, TriggerData};
+use crate::engine::physics::{self, RigidBody};
 use crate::engine::primitives::Polygon;
 use crate::engine::quadtree::QuadTree;
-use glam::Vec2;
+use crate::engine::triggers::{Event, Trigger, Triggers};
+use glam::{Vec2, Vec3};
 use rand::Rng;
+use rand::distr::weighted::WeightedIndex;
 use rand::prelude::*;
 use rand::seq::SliceRandom;
-use std::collections::VecDeque;
+use rand_pcg::Pcg64;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
 use std::time::{Duration, Instant};
 
 const SQRT_3_OVER_2: f32 = 1.732_050_8 / 2.;
+/* How many ticks a stone knocked off the ball flies around before it can be picked up again. */
+const PICKUP_COOLDOWN: u32 = 30;
+/* How many enemies each ambush springs on the player. */
+const AMBUSHERS: usize = 2;
 
+/* A stone that came off the ball, keeping track of the body it is flying around as. */
+#[derive(Serialize, Deserialize)]
+struct LooseStone {
+    stone: Stone,
+    cooldown: u32,
+}
+
+/* What a tile does to the ball when it runs into it. Plain tiles just block, the others each have
+ * their own twist on the collision response.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TileKind {
+    Solid,
+    /* Throws the ball back the way it came. */
+    Bouncy,
+    /* Slows the ball down while it is touching it. */
+    Sticky,
+    /* The ball slides along it without losing any speed, and without knocking stones off. */
+    Ice,
+    /* Burns stones off the ball while it is touching it. */
+    Lava,
+    /* Carries the ball along its surface, counter clockwise around the tile. */
+    Conveyor,
+    /* Crumbles where the ball hits it hard enough. */
+    Breakable,
+}
+
+impl TileKind {
+    pub const ALL: [TileKind; 7] = [
+        TileKind::Solid,
+        TileKind::Bouncy,
+        TileKind::Sticky,
+        TileKind::Ice,
+        TileKind::Lava,
+        TileKind::Conveyor,
+        TileKind::Breakable,
+    ];
+
+    pub fn colour(&self) -> Vec3 {
+        match self {
+            TileKind::Solid => Vec3::ONE,
+            TileKind::Bouncy => Vec3::new(0.3, 0.6, 1.),
+            TileKind::Sticky => Vec3::new(0.6, 0.3, 0.8),
+            TileKind::Ice => Vec3::new(0.75, 0.95, 1.),
+            TileKind::Lava => Vec3::new(1., 0.35, 0.),
+            TileKind::Conveyor => Vec3::new(0.45, 0.45, 0.5),
+            TileKind::Breakable => Vec3::new(0.75, 0.6, 0.45),
+        }
+    }
+}
+
+fn renumber_doors(events: &[Event], handles: &BTreeMap<Handle, Handle>) -> Vec<Event> {
+    events
+        .iter()
+        .map(|event| match event {
+            Event::OpenDoor(tiles) => {
+                Event::OpenDoor(tiles.iter().flat_map(|t| handles.get(t).copied()).collect())
+            }
+            event => event.clone(),
+        })
+        .collect()
+}
+
+/* Everything that goes into generating a level. Later levels are bigger, have fewer tiles carved
+ * out of them, more collectibles to find and more enemies chasing the player.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LevelConfig {
+    pub tile_size: f32,
+    pub dimensions: Vec2,
+    pub num_carvers: usize,
+    /* Carving stops once only this fraction of the original tiles remains. */
+    pub remaining_fraction: f32,
+    pub num_debris: usize,
+    pub num_collectibles: usize,
+    /* How many collectibles need to be picked up to unlock the exit. `None` means all of them. */
+    pub collectibles_to_unlock: Option<usize>,
+    pub num_enemies: usize,
+    /* Hidden spots that spawn more enemies around the player when it rolls over them. */
+    pub num_ambushes: usize,
+    /* How likely each kind of tile is to be placed, relative to the others. */
+    pub tile_weights: Vec<(TileKind, u32)>,
+}
+
+impl LevelConfig {
+    pub fn for_level(level: usize) -> Self {
+        let level_f32 = level as f32;
+        Self {
+            tile_size: 0.2,
+            dimensions: Vec2::splat(2. + level_f32),
+            num_carvers: 10 + level * 2,
+            remaining_fraction: (0.5 + level_f32 * 0.03).min(0.7),
+            num_debris: 20 + level * 5,
+            num_collectibles: 15 + level * 5,
+            collectibles_to_unlock: Some(10 + level * 4),
+            num_enemies: 2 + level,
+            num_ambushes: 1 + level / 2,
+            tile_weights: vec![
+                (TileKind::Solid, 100),
+                (TileKind::Bouncy, 4),
+                (TileKind::Sticky, 4),
+                (TileKind::Ice, 4),
+                (TileKind::Lava, 1 + level as u32),
+                (TileKind::Conveyor, 3),
+                (TileKind::Breakable, 6),
+            ],
+        }
+    }
+
+    /* Reads a config written by hand as text, for generating levels unlike the usual ones. */
+    pub fn load(path: &Path) -> Result<Self, LevelError> {
+        let text = std::fs::read_to_string(path).map_err(LevelError::Io)?;
+        ron::from_str(&text).map_err(LevelError::Text)
+    }
+}
+
+/* The lattice of equilateral triangles that levels are laid out on. Triangle `(column, row)` is
+ * `column` half tiles to the right of the origin and `row` rows above it, and every other
+ * triangle along a row is upside down, starting with an upright one at the origin.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Lattice {
+    pub tile_size: f32,
+    pub origin: Vec2,
+}
+
+impl Lattice {
+    /* The lattice the generator fills, starting from the top left corner of the level. */
+    pub fn for_config(config: &LevelConfig) -> Self {
+        Self {
+            tile_size: config.tile_size,
+            origin: Vec2::new(-config.dimensions.x / 2., config.dimensions.y / 2.),
+        }
+    }
+
+    /* The lattice the given equilateral triangle belongs to. `None` for anything else, such as
+     * the fragments left by carving.
+     */
+    pub fn of(polygon: &Polygon) -> Option<Self> {
+        if polygon.vertices().len() != 3 {
+            return None;
+        }
+        let (min, max) = polygon.aabb();
+        let size = max.x - min.x;
+        let center = (min + max) / 2.;
+        let equilateral = polygon
+            .edges()
+            .all(|(start, end)| (start.distance(*end) - size).abs() < size * 1e-3);
+        if !equilateral {
+            return None;
+        }
+        let upright = polygon.vertices().filter(|v| v.y > center.y).count() == 1;
+        let origin = match upright {
+            true => center,
+            false => center + Vec2::new(size / 2., 0.),
+        };
+        Some(Self {
+            tile_size: size,
+            origin,
+        })
+    }
+
+    /* How far apart the centers of neighbouring columns and rows are. */
+    pub fn spacing(&self) -> Vec2 {
+        Vec2::new(self.tile_size / 2., self.tile_size * SQRT_3_OVER_2)
+    }
+
+    pub fn center(&self, column: i32, row: i32) -> Vec2 {
+        self.origin + Vec2::new(column as f32, row as f32) * self.spacing()
+    }
+
+    pub fn triangle(&self, column: i32, row: i32) -> Polygon {
+        let rotation = match (column + row).rem_euclid(2) == 1 {
+            true => std::f32::consts::PI,
+            false => 0.,
+        };
+        Polygon::new_triangle(self.tile_size, self.center(column, row), rotation)
+    }
+
+    /* The triangle the point falls in, found among the ones around the nearest center. */
+    pub fn triangle_at(&self, point: Vec2) -> Polygon {
+        let (column, row) = ((point - self.origin) / self.spacing()).round().into();
+        let (column, row) = (column as i32, row as i32);
+        [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)]
+            .into_iter()
+            .map(|(c, r)| self.triangle(column + c, row + r))
+            .find(|triangle| triangle.contains_point(point))
+            .unwrap_or_else(|| self.triangle(column, row))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct World {
     polygons: Box<QuadTree>,
+    kinds: BTreeMap<Handle, TileKind>,
+    bodies: Arena<RigidBody>,
+    loose_stones: BTreeMap<Handle, LooseStone>,
+    collectible_spawns: Vec<Vec2>,
+    /* How many collectibles need to be picked up to unlock the exit. `None` means all of them. */
+    collectibles_to_unlock: Option<usize>,
+    enemy_spawns: Vec<Vec2>,
+    cells: Cells,
+    exit: Option<usize>,
+    triggers: Triggers,
+    spawn: Vec2,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new(Box::default(), BTreeMap::new())
+    }
 }
 
 impl World {
-    pub fn generator(tile_size: f32, dimensions: Vec2) -> WorldGenerator {
-        WorldGenerator::new(tile_size, dimensions)
+    /* Generating with the same config and seed always gives the same world. */
+    pub fn generator(config: LevelConfig, seed: u64) -> WorldGenerator {
+        WorldGenerator::new(config, seed)
     }
 
-    fn new(polygons: Box<QuadTree>) -> Self {
-        Self { polygons }
+    fn new(polygons: Box<QuadTree>, kinds: BTreeMap<Handle, TileKind>) -> Self {
+        Self {
+            polygons,
+            kinds,
+            bodies: Arena::new(),
+            loose_stones: BTreeMap::new(),
+            collectible_spawns: vec![],
+            collectibles_to_unlock: None,
+            enemy_spawns: vec![],
+            cells: Cells::default(),
+            exit: None,
+            triggers: Triggers::new(),
+            spawn: Vec2::ZERO,
+        }
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &Polygon> {
         self.polygons.iter()
     }
 
+    /* Every tile along with its kind. */
+    pub fn tiles(&self) -> impl Iterator<Item = (&Polygon, TileKind)> {
+        self.polygons
+            .iter_with_handles()
+            .map(|(handle, polygon)| (polygon, self.kind(handle)))
+    }
+
     pub fn find_in_area(&self, area: &Polygon) -> impl Iterator<Item = &Polygon> {
         self.polygons.find_in_area(area)
     }
+
+    /* Like `find_in_area`, but with the handle and kind of each tile. */
+    pub fn find_tiles_in_area(
+        &self,
+        area: &Polygon,
+    ) -> impl Iterator<Item = (Handle, &Polygon, TileKind)> {
+        self.polygons.find_handles_in_area(area).flat_map(|handle| {
+            self.polygons
+                .get(handle)
+                .map(|polygon| (handle, polygon, self.kind(handle)))
+        })
+    }
+
+    fn kind(&self, handle: Handle) -> TileKind {
+        self.kinds.get(&handle).copied().unwrap_or(TileKind::Solid)
+    }
+
+    /* Cuts the given shape out of the terrain, leaving the remaining fragments in place. The
+     * fragments keep the kind of the tile they were cut from. Tiles cut away altogether become
+     * open cells, so the navigation mesh grows into the space they leave. Returns whether it did.
+     */
+    pub fn carve(&mut self, shape: &Polygon) -> bool {
+        let overlapped: Vec<(Handle, Polygon, TileKind)> = self
+            .find_tiles_in_area(shape)
+            .map(|(handle, polygon, kind)| (handle, polygon.clone(), kind))
+            .collect();
+        let fragments = self.polygons.carve(shape);
+        let polygons = &self.polygons;
+        self.kinds
+            .retain(|handle, _| polygons.get(*handle).is_some());
+        for (original, fragment) in fragments.iter() {
+            if let Some((_, _, kind)) = overlapped.iter().find(|(handle, _, _)| handle == original)
+            {
+                self.kinds.insert(*fragment, *kind);
+            }
+        }
+
+        /* Removed handles can be handed straight back out to fragments, so a tile is only known to
+         * be gone if its handle now holds something else and none of the fragments came from it.
+         */
+        let opened: Vec<Polygon> = overlapped
+            .into_iter()
+            .filter(|(handle, polygon, _)| {
+                self.polygons.get(*handle) != Some(polygon)
+                    && fragments.iter().all(|(original, _)| original != handle)
+            })
+            .map(|(_, polygon, _)| polygon)
+            .collect();
+        if opened.is_empty() {
+            return false;
+        }
+        self.open(opened);
+        true
+    }
+
+    /* Turns the space left behind by removed tiles into open cells. */
+    fn open(&mut self, polygons: Vec<Polygon>) {
+        let cells = self.cells.iter().cloned().chain(polygons).collect();
+        self.cells = Cells::new(cells);
+    }
+
+    /* Adds a tile to the world, like a door. Returns the handles of its convex parts. */
+    pub fn add_tile(&mut self, polygon: Polygon, kind: TileKind) -> Vec<Handle> {
+        let handles = self.polygons.insert(polygon);
+        for handle in handles.iter() {
+            self.kinds.insert(*handle, kind);
+        }
+        handles
+    }
+
+    /* Takes a tile out of the world altogether, like when a door opens, leaving an open cell in
+     * its place.
+     */
+    pub fn break_tile(&mut self, handle: Handle) -> Option<Polygon> {
+        self.kinds.remove(&handle);
+        let polygon = self.polygons.remove(handle)?;
+        self.open(vec![polygon.clone()]);
+        Some(polygon)
+    }
+
+    /* The open cells of the world, as left behind by the generator. */
+    pub fn cells(&self) -> &Cells {
+        &self.cells
+    }
+
+    /* Replaces the navigation mesh, like when the tiles were changed by hand. */
+    pub fn set_cells(&mut self, cells: Cells, exit: Option<usize>) {
+        self.exit = exit.filter(|exit| *exit < cells.len());
+        self.cells = cells;
+    }
+
+    /* The cell the player has to reach to get to the next level. */
+    pub fn exit(&self) -> Option<&Polygon> {
+        self.exit.and_then(|exit| self.cells.get(exit))
+    }
+
+    /* The shortest way through the open cells between two points, as straight line waypoints
+     * starting at `from` and ending at `to`. `None` when either point is outside the open cells or
+     * there is no way between them.
+     */
+    pub fn path(&self, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+        let cells = self.cells.path(from, to)?;
+        Some(self.cells.funnel(from, to, &cells))
+    }
+
+    pub fn add_trigger(&mut self, region: Polygon, trigger: Trigger) -> Handle {
+        self.triggers.insert(region, trigger)
+    }
+
+    pub fn remove_trigger(&mut self, handle: Handle) -> Option<Trigger> {
+        self.triggers.remove(handle)
+    }
+
+    /* The events of the triggers the player entered or left by moving to the given area. */
+    pub fn update_triggers(&mut self, area: &Polygon) -> Vec<Event> {
+        self.triggers.update(area)
+    }
+
+    /* Where the player starts out. */
+    pub fn spawn(&self) -> Vec2 {
+        self.spawn
+    }
+
+    pub fn set_spawn(&mut self, spawn: Vec2) {
+        self.spawn = spawn;
+    }
+
+    /* Open spots picked by the generator for the game to place collectibles on. */
+    pub fn collectible_spawns(&self) -> std::slice::Iter<'_, Vec2> {
+        self.collectible_spawns.iter()
+    }
+
+    pub fn add_collectible_spawn(&mut self, spot: Vec2) {
+        self.collectible_spawns.push(spot);
+    }
+
+    /* Forgets every collectible spot inside the given area. */
+    pub fn remove_collectible_spawns(&mut self, area: &Polygon) {
+        self.collectible_spawns
+            .retain(|spot| !area.contains_point(*spot));
+    }
+
+    pub fn collectibles_to_unlock(&self) -> Option<usize> {
+        self.collectibles_to_unlock
+    }
+
+    /* Open spots picked by the generator for the game to place enemies on. */
+    pub fn enemy_spawns(&self) -> std::slice::Iter<'_, Vec2> {
+        self.enemy_spawns.iter()
+    }
+
+    /* Builds a world out of a level file. The tiles get the handles the file refers to them by. */
+    pub fn from_level(level: &LevelFile) -> Result<Self, LevelError> {
+        let polygon = |vertices: &Vec<Vec2>| Polygon::try_new(vertices.clone());
+
+        let mut world = World::default();
+        for (index, tile) in level.tiles.iter().enumerate() {
+            let tile_polygon = polygon(&tile.vertices)?;
+            if !tile_polygon.is_convex() {
+                return Err(LevelError::NonConvexTile(index));
+            }
+            world.add_tile(tile_polygon, tile.kind);
+        }
+        /* Bodies are drawn and collided as a single convex shape. */
+        for (index, debris) in level.debris.iter().enumerate() {
+            let debris_polygon = polygon(debris)?;
+            if !debris_polygon.is_convex() {
+                return Err(LevelError::NonConvexDebris(index));
+            }
+            world.add_body(RigidBody::new(debris_polygon));
+        }
+        for data in level.triggers.iter() {
+            let regions = data.regions.iter().map(polygon).collect::<Result<_, _>>()?;
+            world.triggers.insert_regions(regions, data.trigger.clone());
+        }
+
+        world.cells = Cells::new(level.cells.iter().map(polygon).collect::<Result<_, _>>()?);
+        if let Some(exit) = level.exit
+            && exit >= world.cells.len()
+        {
+            return Err(LevelError::NoSuchCell(exit));
+        }
+        world.exit = level.exit;
+        world.spawn = level.spawn;
+        world.collectible_spawns = level.collectibles.clone();
+        world.collectibles_to_unlock = level.collectibles_to_unlock;
+        world.enemy_spawns = level.enemies.clone();
+        Ok(world)
+    }
+
+    /* Turns the world back into a level file, as it was when it was generated or loaded. Stones
+     * knocked off the ball are left out. The tiles are numbered afresh, so doors are pointed at
+     * their new handles.
+     */
+    pub fn to_level(&self) -> LevelFile {
+        let mut renumbered = QuadTree::new();
+        let mut handles = BTreeMap::new();
+        let mut tiles = vec![];
+        for (handle, polygon) in self.polygons.iter_with_handles() {
+            for new in renumbered.insert(polygon.clone()) {
+                handles.insert(handle, new);
+            }
+            tiles.push(TileData {
+                vertices: polygon.vertices().copied().collect(),
+                kind: self.kind(handle),
+            });
+        }
+
+        let vertices = |polygon: &Polygon| polygon.vertices().copied().collect::<Vec<Vec2>>();
+        let triggers = self
+            .triggers
+            .iter()
+            .map(|(trigger, regions)| TriggerData {
+                regions: regions.into_iter().map(vertices).collect(),
+                trigger: Trigger {
+                    on_enter: renumber_doors(&trigger.on_enter, &handles),
+                    on_exit: renumber_doors(&trigger.on_exit, &handles),
+                    once: trigger.once,
+                },
+            })
+            .collect();
+
+        LevelFile {
+            version: LEVEL_VERSION,
+            spawn: self.spawn,
+            tiles,
+            cells: self.cells.iter().map(vertices).collect(),
+            exit: self.exit,
+            debris: self
+                .bodies
+                .iter()
+                .filter(|(handle, _)| !self.loose_stones.contains_key(handle))
+                .map(|(_, body)| vertices(body.shape()))
+                .collect(),
+            collectibles: self.collectible_spawns.clone(),
+            enemies: self.enemy_spawns.clone(),
+            triggers,
+            collectibles_to_unlock: self.collectibles_to_unlock,
+        }
+    }
+
+    pub fn add_body(&mut self, body: RigidBody) -> Handle {
+        self.bodies.insert(body)
+    }
+
+    /* Every body in the world, along with the stone it is if it came off the ball. */
+    pub fn bodies(&self) -> impl Iterator<Item = (&RigidBody, Option<&Stone>)> {
+        self.bodies.iter().map(|(handle, body)| {
+            (
+                body,
+                self.loose_stones.get(&handle).map(|loose| &loose.stone),
+            )
+        })
+    }
+
+    /* Turns a stone knocked off the ball into a body flying away with the given velocity. The
+     * stone's position is taken to be relative to `center`.
+     */
+    pub fn add_loose_stone(&mut self, stone: Stone, center: Vec2, velocity: Vec2) -> Handle {
+        let mut shape = stone.polygon();
+        shape.translate(center);
+        let mut body = RigidBody::new(shape);
+        body.apply_impulse(velocity * body.mass(), body.position());
+
+        let handle = self.bodies.insert(body);
+        self.loose_stones.insert(
+            handle,
+            LooseStone {
+                stone,
+                cooldown: PICKUP_COOLDOWN,
+            },
+        );
+        handle
+    }
+
+    /* Removes the loose stones overlapping the given area that can be picked up again, and returns
+     * them along with where they were.
+     */
+    pub fn pick_up_stones(&mut self, area: &Polygon) -> Vec<(Stone, Vec2)> {
+        let picked: Vec<Handle> = self
+            .loose_stones
+            .iter()
+            .filter(|(handle, loose)| {
+                loose.cooldown == 0
+                    && self
+                        .bodies
+                        .get(**handle)
+                        .is_some_and(|body| body.shape().collides_with(area))
+            })
+            .map(|(handle, _)| *handle)
+            .collect();
+
+        picked
+            .into_iter()
+            .flat_map(|handle| {
+                let body = self.bodies.remove(handle)?;
+                let loose = self.loose_stones.remove(&handle)?;
+                Some((loose.stone, body.position()))
+            })
+            .collect()
+    }
+
+    /* Shoves every body overlapping the given area out of the way, as if the area was moving with
+     * the given velocity and could not be stopped.
+     */
+    pub fn push_bodies(&mut self, area: &Polygon, velocity: Vec2) {
+        let handles: Vec<Handle> = self.bodies.iter().map(|(handle, _)| handle).collect();
+        for handle in handles {
+            if let Some(body) = self.bodies.get_mut(handle) {
+                physics::collide_with_kinematic(body, area, velocity);
+            }
+        }
+    }
+
+    /* Moves all the bodies one tick forward, then resolves collisions with the terrain, which is
+     * found through the spatial index, and with each other.
+     */
+    pub fn step(&mut self) {
+        self.loose_stones
+            .values_mut()
+            .for_each(|loose| loose.cooldown = loose.cooldown.saturating_sub(1));
+
+        let handles: Vec<Handle> = self.bodies.iter().map(|(handle, _)| handle).collect();
+        for handle in handles.iter() {
+            if let Some(body) = self.bodies.get_mut(*handle) {
+                body.integrate();
+                let obstacles: Vec<Polygon> =
+                    self.polygons.find_in_area(body.shape()).cloned().collect();
+                for obstacle in obstacles.iter() {
+                    physics::collide_with_static(body, obstacle);
+                }
+            }
+        }
+
+        for (i, a) in handles.iter().enumerate() {
+            for b in handles[i + 1..].iter() {
+                if let Some((lhs, rhs)) = self.bodies.get_pair_mut(*a, *b) {
+                    physics::collide_bodies(lhs, rhs);
+                }
+            }
+        }
+    }
 }
 
+#[derive(Serialize, Deserialize)]
 enum BuildStage {
     GeneratingGrid,
     Carving,
@@ -41,31 +627,43 @@ pub enum GeneratorResult {
     Done(World),
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct WorldGenerator {
-    tile_size: f32,
+    config: LevelConfig,
+    lattice: Lattice,
     dimensions: Vec2,
-    queue: VecDeque<(Vec2, bool, bool)>,
+    /* The triangles of the grid without a tile, either because they were left open around the
+     * start or because a carver removed them. Together they make up the navigation mesh.
+     */
+    open: Vec<Polygon>,
+    /* The column and row of the next triangle of the grid. */
+    queue: VecDeque<(i32, i32)>,
     possible_carvers: Vec<(Vec2, f32)>,
     carvers: VecDeque<(Vec2, f32)>,
+    carved: Vec<Vec2>,
     stage: BuildStage,
     start_num_tiles: usize,
     store: Box<QuadTree>,
+    kinds: BTreeMap<Handle, TileKind>,
+    rng: Pcg64,
 }
 
 impl WorldGenerator {
-    fn new(tile_size: f32, dimensions: Vec2) -> Self {
-        let mut queue = VecDeque::new();
-        let first = Vec2::new(-dimensions.x / 2., dimensions.y / 2.);
-        queue.push_back((first, false, true));
+    fn new(config: LevelConfig, seed: u64) -> Self {
         Self {
-            tile_size,
-            dimensions,
-            queue,
+            lattice: Lattice::for_config(&config),
+            dimensions: config.dimensions,
+            config,
+            open: vec![],
+            queue: VecDeque::from([(0, 0)]),
             possible_carvers: vec![],
             carvers: VecDeque::new(),
+            carved: vec![],
             stage: BuildStage::GeneratingGrid,
             start_num_tiles: 0,
             store: Box::new(QuadTree::new()),
+            kinds: BTreeMap::new(),
+            rng: Pcg64::seed_from_u64(seed),
         }
     }
 
@@ -75,44 +673,64 @@ impl WorldGenerator {
      * the time and exit early if it exceeds its allotted time.
      */
     fn process_queue(&mut self) {
-        if let Some((center, do_rotation, next_row_do_rotation)) = self.queue.pop_front() {
+        if let Some((column, row)) = self.queue.pop_front() {
+            let center = self.lattice.center(column, row);
+            let generated = self.lattice.triangle(column, row);
+
             /* For now, the player will start at the center coordinate, so let's not place any
              * tiles there. This prevents the player from getting immediately trapped.
              */
-            if center.distance(Vec2::ZERO) > self.tile_size {
-                let mut rng = rand::rng();
-                let rotation = match do_rotation {
-                    true => std::f32::consts::PI,
-                    false => 0.,
-                };
-
-                let generated = Polygon::new_triangle(self.tile_size, center, rotation);
-
+            if center.distance(Vec2::ZERO) > self.lattice.tile_size {
                 let midpoints = generated
                     .edges()
                     .map(|(s, e)| s.midpoint(*e))
                     .collect::<Vec<Vec2>>();
-                let midpoint = midpoints.choose(&mut rng).unwrap();
+                let midpoint = midpoints.choose(&mut self.rng).unwrap();
                 let direction = center.angle_to(*midpoint);
                 self.possible_carvers.push((center, direction));
-                self.store.insert(generated);
-            }
-
-            let mut next_center = center + Vec2::new(self.tile_size / 2., 0.);
 
-            let distance = self.tile_size * SQRT_3_OVER_2;
-            if next_center.x > self.dimensions.x / 2. {
-                next_center = Vec2::new(-self.dimensions.x / 2., center.y - distance);
-                if next_center.y < -self.dimensions.y / 2. {
-                    return;
+                let weights = WeightedIndex::new(self.config.tile_weights.iter().map(|(_, w)| w));
+                let kind = weights.map_or(TileKind::Solid, |weights| {
+                    self.config.tile_weights[weights.sample(&mut self.rng)].0
+                });
+                for handle in self.store.insert(generated) {
+                    self.kinds.insert(handle, kind);
                 }
-                self.queue
-                    .push_back((next_center, next_row_do_rotation, !next_row_do_rotation));
             } else {
-                self.queue
-                    .push_back((next_center, !do_rotation, next_row_do_rotation));
+                self.open.push(generated);
+            }
+
+            let (column, row) =
+                match self.lattice.center(column + 1, row).x > self.dimensions.x / 2. {
+                    true => (0, row - 1),
+                    false => (column + 1, row),
+                };
+            if self.lattice.center(column, row).y < -self.dimensions.y / 2. {
+                return;
+            }
+            self.queue.push_back((column, row));
+        }
+    }
+
+    /* The carvers leave a trail of open spots, but they can wander outside of the world, visit the
+     * same spot more than once, or stop between two tiles that are still there. This keeps only
+     * distinct spots inside the world that are clear of tiles.
+     */
+    fn open_spots(&self) -> Vec<Vec2> {
+        let half = self.dimensions * 0.5;
+        let mut spots: Vec<Vec2> = vec![];
+        for carved in self.carved.iter() {
+            let inside = carved.abs().cmple(half).all();
+            let distinct = spots
+                .iter()
+                .all(|spot| spot.distance(*carved) > self.lattice.tile_size * 0.5);
+            let probe = Polygon::new_regular(6, self.lattice.tile_size * 0.15, *carved, 0.);
+            let clear = self.store.find_in_area(&probe).next().is_none();
+            if inside && distinct && clear && carved.length() > self.lattice.tile_size {
+                spots.push(*carved);
             }
         }
+        spots
     }
 
     /* Generates a new world. It incrementally performs the generation steps, checking to see if it
@@ -124,8 +742,6 @@ impl WorldGenerator {
      */
     pub fn generate(mut self, allowed_time: Duration) -> GeneratorResult {
         let start = Instant::now();
-        let mut rng = rand::rng();
-
         loop {
             match self.stage {
                 /* This is building a grid from top-left to lower-right of the dimension of the
@@ -136,34 +752,105 @@ impl WorldGenerator {
                     self.process_queue();
                     if self.queue.is_empty() {
                         self.start_num_tiles = self.store.len();
-                        let mut rng = rand::rng();
-                        self.possible_carvers.shuffle(&mut rng);
-                        self.carvers = VecDeque::from(self.possible_carvers[0..10].to_vec());
+                        self.possible_carvers.shuffle(&mut self.rng);
+                        let num_carvers = self.config.num_carvers.min(self.possible_carvers.len());
+                        self.carvers =
+                            VecDeque::from(self.possible_carvers[0..num_carvers].to_vec());
                         self.stage = BuildStage::Carving;
                     }
                 }
                 /* The carvers remove the tile they are on and then randomly move to a new adjacent
-                 * triangle. When the number of triangles remaining is half or less than the number
-                 * of original triangles, the algorithm is done and the world is ready to be
-                 * rendered.
+                 * triangle. When the fraction of triangles remaining drops to the one given in the
+                 * config, the algorithm is done and the world is ready to be rendered. Some loose
+                 * debris and collectibles are then scattered around the carved out space, and the
+                 * exit is put in the open cell furthest away from the start.
                  */
                 BuildStage::Carving => {
                     if let Some((carver, direction)) = self.carvers.pop_front() {
-                        self.store.remove_from_point(carver);
-                        if self.store.len() as f32 / (self.start_num_tiles as f32) > 0.5 {
+                        self.open.extend(self.store.remove_from_point(carver));
+                        self.carved.push(carver);
+                        if self.store.len() as f32 / (self.start_num_tiles as f32)
+                            > self.config.remaining_fraction
+                        {
                             let next_carver = carver
                                 + Vec2::new(
-                                    f32::cos(direction) * self.tile_size,
-                                    f32::sin(direction) * self.tile_size,
+                                    f32::cos(direction) * self.lattice.tile_size,
+                                    f32::sin(direction) * self.lattice.tile_size,
                                 );
-                            let next_direction = match rng.random() {
+                            let next_direction = match self.rng.random() {
                                 true => direction + std::f32::consts::FRAC_PI_3,
                                 false => direction - std::f32::consts::FRAC_PI_3,
                             };
                             self.carvers.push_back((next_carver, next_direction));
                         }
                     } else {
-                        return GeneratorResult::Done(World::new(self.store));
+                        let mut spots = self.open_spots();
+                        spots.shuffle(&mut self.rng);
+                        let cells = Cells::new(self.open);
+                        let store = self.store;
+                        self.kinds.retain(|handle, _| store.get(*handle).is_some());
+                        let mut world = World::new(store, self.kinds);
+                        for center in spots.iter().take(self.config.num_debris) {
+                            let debris = Polygon::new_regular(
+                                self.rng.random_range(3..=6),
+                                self.lattice.tile_size * 0.15,
+                                *center,
+                                self.rng.random_range(0.0..std::f32::consts::TAU),
+                            );
+                            world.add_body(RigidBody::new(debris));
+                        }
+                        let mut spots = spots.into_iter().skip(self.config.num_debris);
+                        world.collectible_spawns =
+                            spots.by_ref().take(self.config.num_collectibles).collect();
+                        world.collectibles_to_unlock = self.config.collectibles_to_unlock;
+                        world.enemy_spawns = spots.by_ref().take(self.config.num_enemies).collect();
+
+                        let target = world
+                            .collectibles_to_unlock
+                            .unwrap_or(usize::MAX)
+                            .min(world.collectible_spawns.len());
+                        world.add_trigger(
+                            Polygon::new_regular(6, self.lattice.tile_size, Vec2::ZERO, 0.),
+                            Trigger {
+                                on_enter: vec![Event::ShowMessage(format!(
+                                    "Collect {target} treasures to open the exit"
+                                ))],
+                                on_exit: vec![],
+                                once: true,
+                            },
+                        );
+
+                        /* Each ambush springs the enemies hiding at the open spots closest to
+                         * it.
+                         */
+                        let mut spots: Vec<Vec2> = spots.collect();
+                        for _ in 0..self.config.num_ambushes {
+                            let Some(center) = spots.pop() else {
+                                break;
+                            };
+                            spots.sort_by(|a, b| b.distance(center).total_cmp(&a.distance(center)));
+                            let hiding = spots.split_off(spots.len().saturating_sub(AMBUSHERS));
+                            world.add_trigger(
+                                Polygon::new_regular(6, self.lattice.tile_size * 0.5, center, 0.),
+                                Trigger {
+                                    on_enter: vec![
+                                        Event::ShowMessage("Ambush!".to_string()),
+                                        Event::SpawnEnemies(hiding),
+                                    ],
+                                    on_exit: vec![],
+                                    once: true,
+                                },
+                            );
+                        }
+                        world.exit = cells
+                            .distance_field(Vec2::ZERO)
+                            .iter()
+                            .enumerate()
+                            .filter_map(|(cell, distance)| distance.map(|d| (d, cell)))
+                            .max()
+                            .map(|(_, cell)| cell);
+                        world.cells = cells;
+                        return GeneratorResult::Done(world);
                     }
                 }
             }
@@ -175,3 +862,135 @@ impl WorldGenerator {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::level::TriggerData;
+
+    fn generate(config: LevelConfig) -> World {
+        let mut generator = World::generator(config, 1);
+        loop {
+            match generator.generate(Duration::from_millis(100)) {
+                GeneratorResult::Generating(next) => generator = next,
+                GeneratorResult::Done(world) => return world,
+            }
+        }
+    }
+
+    #[test]
+    fn test_exit_is_furthest_open_cell() {
+        let world = generate(LevelConfig::for_level(0));
+        let exit = world.exit().expect("No exit was placed");
+
+        let distances = world.cells().distance_field(Vec2::ZERO);
+        let exit_cell = world.cells().cell_at(exit.centroid()).unwrap();
+        let furthest = distances.iter().flatten().max().unwrap();
+        assert_eq!(distances[exit_cell].as_ref(), Some(furthest));
+        assert!(
+            world
+                .find_in_area(exit)
+                .all(|tile| exit.contact(tile).unwrap().1 < 1e-4)
+        );
+    }
+
+    #[test]
+    fn test_path_to_exit() {
+        let world = generate(LevelConfig::for_level(0));
+        let exit = world.exit().unwrap().centroid();
+        let path = world
+            .path(Vec2::ZERO, exit)
+            .expect("The exit cannot be reached");
+
+        assert_eq!(path.first(), Some(&Vec2::ZERO));
+        assert_eq!(path.last(), Some(&exit));
+        for pair in path.windows(2) {
+            /* Paths hug the corners, so they can run right along the edges of the cells. */
+            let probe = Polygon::new_regular(6, 1e-4, pair[0].midpoint(pair[1]), 0.);
+            assert!(world.cells().iter().any(|cell| cell.collides_with(&probe)));
+        }
+    }
+
+    #[test]
+    fn test_tile_kinds() {
+        let config = LevelConfig {
+            tile_weights: vec![(TileKind::Solid, 0), (TileKind::Lava, 1)],
+            ..LevelConfig::for_level(0)
+        };
+        let mut world = generate(config);
+        assert!(world.tiles().all(|(_, kind)| kind == TileKind::Lava));
+
+        let (tile, _) = world.tiles().next().unwrap();
+        let hole = Polygon::new_regular(6, 0.05, tile.vertices().next().copied().unwrap(), 0.);
+        world.carve(&hole);
+        assert!(world.tiles().all(|(_, kind)| kind == TileKind::Lava));
+
+        /* Cutting a whole tile away leaves an open cell where it was. */
+        let cells = world.cells().len();
+        let (tile, _) = world.tiles().nth(10).unwrap();
+        let center = tile.centroid();
+        assert!(world.cells().cell_at(center).is_none());
+        assert!(world.carve(&Polygon::new_regular(6, 0.3, center, 0.)));
+        assert!(world.cells().len() > cells);
+        assert!(world.cells().cell_at(center).is_some());
+        assert!(world.tiles().all(|(_, kind)| kind == TileKind::Lava));
+
+        let (handle, _, _) = world.find_tiles_in_area(&hole).next().unwrap();
+        let count = world.tiles().count();
+        let cells = world.cells().len();
+        let broken = world.break_tile(handle).unwrap();
+        assert_eq!(world.tiles().count(), count - 1);
+        assert_eq!(world.cells().len(), cells + 1);
+        assert!(world.cells().cell_at(broken.centroid()).is_some());
+    }
+
+    #[test]
+    fn test_level_round_trip() {
+        let mut world = generate(LevelConfig::for_level(0));
+        let door_shape = Polygon::new_regular(4, 0.1, Vec2::new(5., 5.), 0.);
+        let door = world.add_tile(door_shape.clone(), TileKind::Breakable);
+        world.add_trigger(
+            Polygon::new_regular(6, 0.1, Vec2::new(5., 4.), 0.),
+            Trigger {
+                on_enter: vec![Event::OpenDoor(door)],
+                on_exit: vec![],
+                once: true,
+            },
+        );
+
+        let level = world.to_level();
+        let text = LevelFile::from_ron(&level.to_ron().unwrap()).unwrap();
+        let binary = LevelFile::from_bytes(&level.to_bytes().unwrap()).unwrap();
+        assert_eq!(text, level);
+        assert_eq!(binary, level);
+
+        let loaded = World::from_level(&level).unwrap();
+        assert_eq!(loaded.to_level(), level);
+        assert_eq!(loaded.tiles().count(), world.tiles().count());
+        assert_eq!(loaded.cells().len(), world.cells().len());
+        assert_eq!(loaded.exit(), world.exit());
+
+        let Some(TriggerData { trigger, .. }) = level.triggers.last() else {
+            panic!("The door trigger went missing");
+        };
+        let Event::OpenDoor(tiles) = &trigger.on_enter[0] else {
+            panic!("The door trigger lost its event");
+        };
+        assert_eq!(loaded.polygons.get(tiles[0]), Some(&door_shape));
+
+        let mut concave = level.clone();
+        concave.debris.insert(
+            0,
+            vec![
+                Vec2::new(0., 0.),
+                Vec2::new(0.1, 0.),
+                Vec2::new(0.05, 0.02),
+                Vec2::new(0.05, 0.1),
+            ],
+        );
+        assert!(matches!(
+            World::from_level(&concave),
+            Err(LevelError::NonConvexDebris(0))
+        ));
+    }
+}
@@ -1,15 +1,26 @@
-mod engine;
-
-use crate::engine::game::{Command, Game};
 extern crate sdl2;
 
+mod cli;
+
+use cli::Cli;
 use glam::{Vec2, Vec3};
+use rollroll::engine::batch::Batch;
+use rollroll::engine::editor::{Editor, Tool};
+use rollroll::engine::game::{Command, Game};
+use rollroll::engine::replay::Replay;
+use rollroll::engine::svg::commands_to_svg;
+use rollroll::engine::triggers::Event as GameEvent;
+use rollroll::engine::world::{LevelConfig, TileKind};
 use sdl2::controller::{Axis, GameController};
 use sdl2::event::{Event, WindowEvent};
 use sdl2::gfx::primitives::DrawRenderer;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
 use sdl2::pixels::Color;
-use std::time::Duration;
+use sdl2::rect::FPoint;
+use sdl2::render::Vertex;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 fn vec3_to_color(normalized_color: &Vec3) -> Color {
     Color::RGB(
@@ -21,6 +32,7 @@ fn vec3_to_color(normalized_color: &Vec3) -> Color {
 
 /* For some reason, at least on my device, SDL2_gfx functions think that colors are AABBGGRR
  * arranged in memory. I need to make a reversed version in order for the colors to look right.
+ * Only the text is still drawn with SDL2_gfx, everything else goes through `render_geometry`.
  */
 fn vec3_to_color_reversed(normalized_color: &Vec3) -> Color {
     Color::RGBA(
@@ -31,19 +43,48 @@ fn vec3_to_color_reversed(normalized_color: &Vec3) -> Color {
     )
 }
 
-fn logical_coordinates(point: &Vec2, (window_w, window_h): (i32, i32)) -> (i32, i32) {
+fn logical_coordinates(point: &Vec2, (window_w, window_h): (i32, i32)) -> FPoint {
     let dimension = window_w.max(window_h) as f32;
-    (
-        (dimension * ((point.x + 1.) * 0.5) - (dimension - window_w as f32) * 0.5) as i32,
-        (dimension * ((-point.y + 1.) * 0.5) - (dimension - window_h as f32) * 0.5) as i32,
+    FPoint::new(
+        dimension * ((point.x + 1.) * 0.5) - (dimension - window_w as f32) * 0.5,
+        dimension * ((-point.y + 1.) * 0.5) - (dimension - window_h as f32) * 0.5,
     )
 }
 
-fn logical_length(length: &f32, (window_w, window_h): (i32, i32)) -> i32 {
+/* The inverse of `logical_coordinates`, for turning the mouse position into game coordinates. */
+fn game_coordinates((x, y): (i32, i32), (window_w, window_h): (i32, i32)) -> Vec2 {
     let dimension = window_w.max(window_h) as f32;
-    (dimension * ((length + 1.) * 0.5) - dimension * 0.5) as i32
+    Vec2::new(
+        (x as f32 + (dimension - window_w as f32) * 0.5) / dimension * 2. - 1.,
+        -((y as f32 + (dimension - window_h as f32) * 0.5) / dimension * 2. - 1.),
+    )
 }
 
+/* F5 saves the game here and F9 picks it back up. */
+const QUICKSAVE_PATH: &str = "rollroll.sav";
+
+/* F12 saves the frame on screen here, as a picture that does not depend on the resolution. */
+const SNAPSHOT_PATH: &str = "rollroll.svg";
+
+/* Levels made in the editor are saved here, unless a level file was given to edit. */
+const EDITED_LEVEL_PATH: &str = "edited.ron";
+/* How far the arrow keys move the editor camera, in screen coordinates. */
+const EDITOR_PAN: f32 = 0.1;
+const EDITOR_ZOOM: f32 = 1.1;
+/* The number keys pick the kind of tile the editor places. */
+const EDITOR_TILE_KEYS: [(Keycode, TileKind); 7] = [
+    (Keycode::Num1, TileKind::Solid),
+    (Keycode::Num2, TileKind::Bouncy),
+    (Keycode::Num3, TileKind::Sticky),
+    (Keycode::Num4, TileKind::Ice),
+    (Keycode::Num5, TileKind::Lava),
+    (Keycode::Num6, TileKind::Conveyor),
+    (Keycode::Num7, TileKind::Breakable),
+];
+
+/* How long a message from the game stays on screen. */
+const MESSAGE_DURATION: Duration = Duration::from_secs(3);
+
 const AXIS_THRESHOLD: i16 = 3000;
 fn normalize_axis(value: i16) -> f32 {
     if (-AXIS_THRESHOLD..AXIS_THRESHOLD).contains(&value) {
@@ -55,7 +96,57 @@ fn normalize_axis(value: i16) -> f32 {
     (2.0 * (v - min) / (max - min)) - 1.0
 }
 
+/* Runs the game without SDL at all, for as many ticks as asked for or until the replay is over. */
+fn run_headless(
+    cli: &Cli,
+    mut game: Game,
+    mut replay: Option<Replay>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut recording = match cli.record {
+        Some(_) => Some(Replay::new(&game)?),
+        None => None,
+    };
+    let mut command_arena: Vec<Command> = Vec::new();
+    let mut event_arena: Vec<GameEvent> = Vec::new();
+    let mut ticks = 0;
+    while cli.ticks.is_none_or(|limit| ticks < limit) {
+        let movement = match replay.as_mut().map(|replay| replay.play(&game)) {
+            Some(None) if cli.ticks.is_none() => break,
+            Some(Some(movement)) => movement,
+            _ => Vec2::ZERO,
+        };
+        if let Some(ref mut recording) = recording {
+            recording.record(&game, movement);
+        }
+        (game, command_arena, event_arena) = game.tick(&movement, command_arena, event_arena);
+        for event in event_arena.iter() {
+            if let GameEvent::ShowMessage(text) = event {
+                println!("{}", text);
+            }
+        }
+        ticks += 1;
+    }
+
+    if let (Some(path), Some(recording)) = (&cli.record, &recording) {
+        recording.save(path)?;
+    }
+    println!(
+        "Ran {} ticks, ending on level {} with a score of {}",
+        ticks,
+        game.level(),
+        game.score()
+    );
+    Ok(())
+}
+
 pub fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cli = Cli::parse_and_validate();
+    let mut game = cli.game()?;
+    let mut replay = cli.replay.take();
+    if cli.headless {
+        return run_headless(&cli, game, replay);
+    }
+
     /* https://github.com/Rust-SDL2/rust-sdl2/blob/master/examples/game-controller.rs
      *
      * This says that the below line is necessary for some controllers to work on Windows.
@@ -66,22 +157,53 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
     let video_subsystem = sdl_context.video()?;
     let game_controller_subsystem = sdl_context.game_controller()?;
 
-    let window = video_subsystem
-        .window("RollRoll", 800, 600)
-        .opengl()
-        .fullscreen_desktop()
-        .build()
-        .map_err(|e| e.to_string())?;
+    /* Multisampling only applies to OpenGL, so the renderer has to be the OpenGL one for it. */
+    let mut window_builder = video_subsystem.window("RollRoll", cli.width, cli.height);
+    if cli.msaa > 0 {
+        sdl2::hint::set("SDL_RENDER_DRIVER", "opengl");
+        let gl_attr = video_subsystem.gl_attr();
+        gl_attr.set_multisample_buffers(1);
+        gl_attr.set_multisample_samples(cli.msaa);
+        window_builder.opengl();
+    }
+    if !cli.windowed {
+        window_builder.fullscreen_desktop();
+    }
+    let window = window_builder.build().map_err(|e| e.to_string())?;
 
-    let mut canvas = window.into_canvas().build()?;
+    let mut canvas = match cli.vsync {
+        true => window.into_canvas().present_vsync().build()?,
+        false => window.into_canvas().build()?,
+    };
 
     let mut event_pump = sdl_context.event_pump()?;
     let mut command_arena: Vec<Command> = Vec::new();
-    let mut window_size: (i32, i32) = (0, 0);
-    let mut game = Game::new();
+    let mut event_arena: Vec<GameEvent> = Vec::new();
+    let mut batch = Batch::new();
+    let mut geometry: Vec<Vertex> = Vec::new();
+    let mut message: Option<(String, Instant)> = None;
+    /* A window that is not resized gets no event saying how big it is. */
+    let (window_w, window_h) = canvas.output_size()?;
+    let mut window_size: (i32, i32) = (window_w as i32, window_h as i32);
+    /* Restoring or loading another game starts the recording over from it, and stops a replay,
+     * which would not make sense for the new game.
+     */
+    let mut recording = match cli.record {
+        Some(_) => Some(Replay::new(&game)?),
+        None => None,
+    };
+    let mut replaced = false;
+    /* E switches to the editor with the level being played, and back to playing what was made. */
+    let mut editor: Option<Editor> = None;
+    let edited_path = match cli.level {
+        Some(ref level) => level.path.clone(),
+        None => EDITED_LEVEL_PATH.into(),
+    };
+    let mut cursor = Vec2::ZERO;
 
     let mut controller: Option<GameController> = None;
     let mut movement = Vec2::ZERO;
+    let mut paused = false;
 
     'running: loop {
         for event in event_pump.poll_iter() {
@@ -91,6 +213,132 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } => paused = !paused,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => match game.save_to(Path::new(QUICKSAVE_PATH)) {
+                    Ok(()) => message = Some(("Game saved".to_string(), Instant::now())),
+                    Err(e) => eprintln!("{}", e),
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => match Game::restore_from(Path::new(QUICKSAVE_PATH)) {
+                    Ok(restored) => {
+                        game = restored;
+                        replaced = true;
+                        message = Some(("Game restored".to_string(), Instant::now()));
+                    }
+                    Err(e) => eprintln!("{}", e),
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    ..
+                } => match std::fs::write(SNAPSHOT_PATH, commands_to_svg(&command_arena)) {
+                    Ok(()) => {
+                        let text = format!("Frame saved to {}", SNAPSHOT_PATH);
+                        message = Some((text, Instant::now()));
+                    }
+                    Err(e) => eprintln!("{}", e),
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::E),
+                    ..
+                } => match editor.take() {
+                    Some(edited) => match Game::load(0, &edited.to_level()) {
+                        Ok(loaded) => {
+                            game = loaded;
+                            replaced = true;
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            editor = Some(edited);
+                        }
+                    },
+                    None => {
+                        let config = LevelConfig::for_level(game.level());
+                        let opened = game
+                            .level_file()
+                            .map(|level| Editor::from_level(&level, config.tile_size));
+                        editor = match opened {
+                            Some(Ok(opened)) => Some(opened),
+                            Some(Err(e)) => {
+                                eprintln!("{}", e);
+                                None
+                            }
+                            None => Some(Editor::new(&config)),
+                        };
+                    }
+                },
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } if editor.is_some() => {
+                    let Some(ref mut editor) = editor else {
+                        continue;
+                    };
+                    if let Some((_, kind)) = EDITOR_TILE_KEYS.iter().find(|(k, _)| *k == keycode) {
+                        editor.set_tool(Tool::Tile(*kind));
+                    }
+                    match keycode {
+                        Keycode::S if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                            match editor.to_level().save(&edited_path) {
+                                Ok(()) => {
+                                    let text = format!("Saved to {}", edited_path.display());
+                                    message = Some((text, Instant::now()));
+                                }
+                                Err(e) => eprintln!("{}", e),
+                            }
+                        }
+                        Keycode::S => editor.set_tool(Tool::Spawn),
+                        Keycode::X => editor.set_tool(Tool::Exit),
+                        Keycode::C => editor.set_tool(Tool::Collectible),
+                        Keycode::D => editor.set_tool(Tool::Erase),
+                        Keycode::Left => editor.pan(Vec2::new(-EDITOR_PAN, 0.)),
+                        Keycode::Right => editor.pan(Vec2::new(EDITOR_PAN, 0.)),
+                        Keycode::Up => editor.pan(Vec2::new(0., EDITOR_PAN)),
+                        Keycode::Down => editor.pan(Vec2::new(0., -EDITOR_PAN)),
+                        _ => {}
+                    }
+                }
+                Event::MouseMotion {
+                    x, y, mousestate, ..
+                } => {
+                    cursor = game_coordinates((x, y), window_size);
+                    /* Dragging paints tiles, so long walls do not take a click per triangle. */
+                    if let Some(ref mut editor) = editor
+                        && matches!(editor.tool(), Tool::Tile(_))
+                    {
+                        if mousestate.left() {
+                            editor.apply(editor.screen_to_world(cursor));
+                        } else if mousestate.right() {
+                            editor.erase(editor.screen_to_world(cursor));
+                        }
+                    }
+                }
+                Event::MouseButtonDown {
+                    mouse_btn, x, y, ..
+                } => {
+                    cursor = game_coordinates((x, y), window_size);
+                    if let Some(ref mut editor) = editor {
+                        let point = editor.screen_to_world(cursor);
+                        match mouse_btn {
+                            MouseButton::Left => editor.apply(point),
+                            MouseButton::Right => editor.erase(point),
+                            _ => {}
+                        }
+                    }
+                }
+                Event::MouseWheel { y, .. } => {
+                    if let Some(ref mut editor) = editor {
+                        editor.zoom_by(EDITOR_ZOOM.powi(y));
+                    }
+                }
                 Event::Window {
                     win_event: WindowEvent::Resized(x, y),
                     ..
@@ -125,38 +373,73 @@ pub fn main() -> Result<(), Box<dyn std::error::Error>> {
                 _ => {}
             }
         }
-        (game, command_arena) = game.tick(&movement, command_arena);
-
-        for command in command_arena.iter() {
-            match command {
-                Command::Clear(normalized_color) => {
-                    let color = vec3_to_color(normalized_color);
-                    canvas.set_draw_color(color);
-                    canvas.clear();
-                }
-                Command::RenderCircle((p, r, normalized_color)) => {
-                    let color = vec3_to_color_reversed(normalized_color);
-                    let point = logical_coordinates(p, window_size);
-                    let radius = logical_length(r, window_size);
-                    canvas.filled_circle(point.0 as i16, point.1 as i16, radius as i16, color)?;
-                }
-                Command::RenderFilledPolygon((vertices, normalized_color)) => {
-                    let color = vec3_to_color_reversed(normalized_color);
-                    let (logical_x, logical_y): (Vec<i16>, Vec<i16>) = vertices
-                        .iter()
-                        .map(|v| {
-                            let (x, y) = logical_coordinates(v, window_size);
-                            (x as i16, y as i16)
-                        })
-                        .unzip();
-
-                    canvas.filled_polygon(&logical_x[0..], &logical_y[0..], color)?;
+        if replaced {
+            replaced = false;
+            replay = None;
+            if recording.is_some() {
+                recording = Some(Replay::new(&game)?);
+            }
+        }
+
+        /* While paused, the last frame just keeps being drawn. */
+        if let Some(ref editor) = editor {
+            editor.render(cursor, &mut command_arena);
+            event_arena.clear();
+        } else if !paused {
+            let movement = match replay.as_mut().map(|replay| replay.play(&game)) {
+                Some(Some(replayed)) => replayed,
+                Some(None) => {
+                    replay = None;
+                    message = Some(("Replay finished".to_string(), Instant::now()));
+                    movement
                 }
+                None => movement,
+            };
+            if let Some(ref mut recording) = recording {
+                recording.record(&game, movement);
+            }
+            (game, command_arena, event_arena) = game.tick(&movement, command_arena, event_arena);
+        } else {
+            event_arena.clear();
+        }
+        for event in event_arena.iter() {
+            if let GameEvent::ShowMessage(text) = event {
+                message = Some((text.clone(), Instant::now()));
+            }
+        }
+
+        /* The whole frame goes to the GPU as one list of triangles. */
+        batch.fill(&command_arena);
+        canvas.set_draw_color(vec3_to_color(&batch.clear_colour()));
+        canvas.clear();
+        geometry.clear();
+        geometry.extend(batch.vertices().map(|(position, colour)| Vertex {
+            position: logical_coordinates(position, window_size),
+            color: vec3_to_color(colour),
+            tex_coord: FPoint::new(0., 0.),
+        }));
+        canvas.render_geometry(&geometry, None, batch.indices())?;
+
+        if let Some((ref text, shown)) = message {
+            if shown.elapsed() < MESSAGE_DURATION {
+                /* The built in font of SDL2_gfx is 8 pixels per character. */
+                let x = (window_size.0 - text.len() as i32 * 8) / 2;
+                let y = window_size.1 / 8;
+                canvas.string(x as i16, y as i16, text, vec3_to_color_reversed(&Vec3::ONE))?;
+            } else {
+                message = None;
             }
         }
 
         canvas.present();
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        /* With vsync, presenting already waits for the next frame. */
+        if !cli.vsync {
+            ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        }
+    }
+
+    if let (Some(path), Some(recording)) = (&cli.record, &recording) {
+        recording.save(path)?;
     }
     Ok(())
 }